@@ -0,0 +1,141 @@
+use conv::*;
+use rand::{Rng, SeedableRng};
+
+use crate::{CountUnique, DistinctCounter};
+
+/// Return the number of groups for which the median of the group means fails
+/// with probability at most `delta`, provided that each group mean fails with
+/// probability at most 1/4: ⌈8·ln(1/δ)⌉, rounded up to an odd number.
+///
+/// # Panics
+/// Function will panic unless `0 < delta < 1`.
+pub fn groups_for(delta: f64) -> usize {
+    if !(delta > 0.0 && delta < 1.0) {
+        panic!("Invalid failure probability");
+    }
+    let k = (8.0 * (1.0 / delta).ln()).ceil() as usize;
+    k.max(1) | 1
+}
+
+/// A median-of-means estimator that runs several independent `CountUnique`
+/// sketches and returns the median of the means of groups of sketches.
+///
+/// Averaging the `group_size` sketches in a group reduces the variance of
+/// the estimate, and taking the median of the groups makes large errors
+/// exponentially unlikely: if each group mean is within the desired error
+/// with probability at least 3/4, the median of `groups_for(delta)` groups is
+/// within it with probability at least `1 - delta`. By Chebyshev's inequality
+/// a group mean is within a factor `(1 ± epsilon)` of the count with
+/// probability at least 3/4 when the relative variance of one sketch, about
+/// `1/sz`, is at most `group_size·epsilon²/4`.
+///
+/// Every token is added to every sketch, so adding a token costs
+/// `groups·group_size` times as much as for one sketch.
+///
+/// # Example
+/// ```
+/// use count_unique_cvm::*;
+/// use rand::rngs::StdRng;
+///
+/// let mut c: BoostedCountUnique<u64, StdRng> =
+///     BoostedCountUnique::new(42, 100, groups_for(0.01), 2);
+/// for i in 0..1000 {
+///     c.add_token(i);
+/// }
+/// println!("Estimated number of tokens are {}", c.estimate().unwrap());
+/// ```
+pub struct BoostedCountUnique<T: Ord + Clone, R: Rng + SeedableRng> {
+    sketches: Vec<CountUnique<T, R>>,
+    group_size: usize,
+}
+
+impl<T, R> BoostedCountUnique<T, R>
+where
+    T: Ord + Clone,
+    R: Rng + SeedableRng,
+{
+    /// Create a new `BoostedCountUnique` with `groups` groups of `group_size`
+    /// sketches, each storing `sz` elements. The random number generator of
+    /// each sketch is seeded from a generator seeded with `seed`.
+    ///
+    /// # Panics
+    /// Function will panic if `sz`, `groups` or `group_size` is 0.
+    pub fn new(seed: u64, sz: usize, groups: usize, group_size: usize) -> Self {
+        if groups < 1 || group_size < 1 {
+            panic!("Need at least one sketch");
+        }
+        let mut seeds = R::seed_from_u64(seed);
+        let sketches = (0..groups * group_size)
+            .map(|_| CountUnique::new(R::seed_from_u64(seeds.gen()), sz))
+            .collect();
+        BoostedCountUnique {
+            sketches,
+            group_size,
+        }
+    }
+
+    /// Return the number of groups.
+    pub fn groups(&self) -> usize {
+        self.sketches.len() / self.group_size
+    }
+
+    /// Return the number of sketches in each group.
+    pub fn group_size(&self) -> usize {
+        self.group_size
+    }
+
+    /// Add a token to every sketch.
+    pub fn add_token(&mut self, t: T) {
+        let (last, rest) = self.sketches.split_last_mut().unwrap();
+        for s in rest {
+            s.add_token(t.clone());
+        }
+        last.add_token(t);
+    }
+
+    /// Return the median of the group means of the sketches' estimates.
+    pub fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
+        let mut means = Vec::with_capacity(self.groups());
+        for g in self.sketches.chunks(self.group_size) {
+            let mut sum = 0.0;
+            for s in g {
+                sum += s.estimate()?;
+            }
+            means.push(sum / self.group_size as f64);
+        }
+        means.sort_by(f64::total_cmp);
+        let mid = means.len() / 2;
+        if means.len() % 2 == 1 {
+            Ok(means[mid])
+        } else {
+            Ok((means[mid - 1] + means[mid]) / 2.0)
+        }
+    }
+
+    /// Reset every sketch allowing the structure to be reused.
+    pub fn reset(&mut self) {
+        for s in &mut self.sketches {
+            s.reset();
+        }
+    }
+}
+
+impl<T, R> DistinctCounter<T> for BoostedCountUnique<T, R>
+where
+    T: Ord + Clone,
+    R: Rng + SeedableRng,
+{
+    type Error = PosOverflow<usize>;
+
+    fn add_token(&mut self, t: T) {
+        BoostedCountUnique::add_token(self, t)
+    }
+
+    fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
+        BoostedCountUnique::estimate(self)
+    }
+
+    fn reset(&mut self) {
+        BoostedCountUnique::reset(self)
+    }
+}
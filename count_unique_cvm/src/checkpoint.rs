@@ -0,0 +1,197 @@
+//! Saving and restoring `CountUnique` state.
+//!
+//! `CountUnique::checkpoint()` captures the sampled tokens and their
+//! priorities, the sampling probability, the buffer size and the configured
+//! guarantee as a `Checkpoint`, which implements serde's `Serialize` and
+//! `Deserialize` and can also be written in a compact binary format using
+//! `Checkpoint::to_bytes()`. `CountUnique::restore()` rebuilds the sketch from
+//! a checkpoint and a priority source.
+//!
+//! A checkpoint does not include the random number generator. When `R`
+//! implements `Serialize` and `Deserialize`, `CountUnique` itself does too and
+//! also saves the generator state, so that a restored sketch continues exactly
+//! where the original left off. `rand::rngs::StdRng` does not expose its state;
+//! use `rand_chacha::ChaCha12Rng` (the generator behind `StdRng`) with
+//! `rand_chacha`'s `serde1` feature instead.
+
+use std::fmt::{Display, Formatter};
+
+use bincode::Options;
+use serde::de::{Deserializer, Error as DeError};
+use serde::ser::Serializer;
+use serde::{Deserialize, Serialize};
+
+use crate::{CountUnique, PrioritySource};
+use treap_non_random::{Element, Treap};
+
+/// The version of the checkpoint format written by this library.
+pub const CHECKPOINT_VERSION: u16 = 1;
+
+/// Magic bytes at the start of the binary format.
+const MAGIC: &[u8; 4] = b"CVMS";
+
+/// The `bincode` options used by the binary format. Input must be consumed
+/// entirely, so that trailing bytes are an error.
+fn options() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .reject_trailing_bytes()
+}
+
+/// The state of a `CountUnique` sketch, without its priority source.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Checkpoint<T> {
+    version: u16,
+    max_size: usize,
+    p: f64,
+    guarantee: Option<(f64, f64)>,
+    // Sampled tokens and their priorities, in token order.
+    samples: Vec<(T, f64)>,
+}
+
+/// Errors returned when restoring a checkpoint.
+#[derive(Debug)]
+pub enum CheckpointError {
+    /// The input does not start with the checkpoint magic bytes.
+    BadMagic,
+    /// The checkpoint was written by an unsupported version of the format.
+    UnsupportedVersion(u16),
+    /// The binary encoding could not be decoded.
+    Encoding(bincode::Error),
+    /// The checkpoint does not describe a valid sketch.
+    Invalid(&'static str),
+}
+
+impl Display for CheckpointError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheckpointError::BadMagic => write!(f, "not a CVM checkpoint"),
+            CheckpointError::UnsupportedVersion(v) => {
+                write!(f, "unsupported checkpoint version {}", v)
+            }
+            CheckpointError::Encoding(e) => write!(f, "could not decode checkpoint: {}", e),
+            CheckpointError::Invalid(m) => write!(f, "invalid checkpoint: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl<T: Serialize> Checkpoint<T> {
+    /// Encode the checkpoint in the binary format: the magic bytes `CVMS`,
+    /// followed by the format version and the state, encoded with `bincode`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, CheckpointError> {
+        let mut bytes = MAGIC.to_vec();
+        options()
+            .serialize_into(&mut bytes, self)
+            .map_err(CheckpointError::Encoding)?;
+        Ok(bytes)
+    }
+}
+
+impl<T: for<'de> Deserialize<'de>> Checkpoint<T> {
+    /// Decode a checkpoint written by `to_bytes()`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, CheckpointError> {
+        let body = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or(CheckpointError::BadMagic)?;
+        let c: Self = options()
+            .deserialize(body)
+            .map_err(CheckpointError::Encoding)?;
+        if c.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(c.version));
+        }
+        Ok(c)
+    }
+}
+
+impl<T, R> CountUnique<T, R>
+where
+    T: Ord + Clone,
+    R: PrioritySource<T>,
+{
+    /// Capture the state of the sketch, except for its priority source.
+    pub fn checkpoint(&self) -> Checkpoint<T> {
+        Checkpoint {
+            version: CHECKPOINT_VERSION,
+            max_size: self.max_size,
+            p: self.p,
+            guarantee: self.guarantee,
+            samples: self
+                .treap
+                .iter()
+                .map(|e| (e.value().clone(), *e.priority()))
+                .collect(),
+        }
+    }
+
+    /// Rebuild a sketch from a checkpoint, drawing future priorities from
+    /// `r`. When the sketch uses `Hashed` priorities, `r` must use the same
+    /// hasher as the original sketch.
+    pub fn restore(r: R, c: Checkpoint<T>) -> Result<Self, CheckpointError> {
+        if c.version != CHECKPOINT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(c.version));
+        }
+        if c.max_size < 1 {
+            return Err(CheckpointError::Invalid("buffer size must be non-zero"));
+        }
+        if !(c.p > 0.0 && c.p <= 1.0) {
+            return Err(CheckpointError::Invalid("p must be in (0, 1]"));
+        }
+        if c.samples.len() > c.max_size {
+            return Err(CheckpointError::Invalid("too many samples"));
+        }
+        if c.samples.iter().any(|(_, u)| !(*u >= 0.0 && *u < c.p)) {
+            return Err(CheckpointError::Invalid(
+                "sample priority must be in [0, p)",
+            ));
+        }
+        // Priorities were checked above, so only the order can be invalid.
+        let treap = Treap::from_sorted(c.samples.into_iter().map(|(t, u)| Element::new(t, u)))
+            .map_err(|_| CheckpointError::Invalid("samples are not sorted"))?;
+        Ok(CountUnique {
+            treap,
+            rng: r,
+            max_size: c.max_size,
+            p: c.p,
+            guarantee: c.guarantee,
+        })
+    }
+}
+
+/// The full state of a sketch, including its priority source.
+#[derive(Serialize, Deserialize)]
+struct State<T, R> {
+    sketch: Checkpoint<T>,
+    rng: R,
+}
+
+impl<T, R> Serialize for CountUnique<T, R>
+where
+    T: Ord + Clone + Serialize,
+    R: PrioritySource<T> + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct StateRef<'a, T, R> {
+            sketch: Checkpoint<T>,
+            rng: &'a R,
+        }
+        StateRef {
+            sketch: self.checkpoint(),
+            rng: &self.rng,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T, R> Deserialize<'de> for CountUnique<T, R>
+where
+    T: Ord + Clone + Deserialize<'de>,
+    R: PrioritySource<T> + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = State::<T, R>::deserialize(deserializer)?;
+        CountUnique::restore(s.rng, s.sketch).map_err(D::Error::custom)
+    }
+}
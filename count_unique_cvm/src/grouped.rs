@@ -0,0 +1,225 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::hash::{BuildHasher, Hash};
+use std::mem;
+
+use crate::{Hashed, PrioritySource};
+use treap_non_random::{Element, Treap};
+
+struct Group<T: Ord> {
+    treap: Treap<T, f64>,
+    p: f64,
+    // Whether every token of the group was added to this sample, i.e., the
+    // group cannot have been dropped before.
+    complete: bool,
+}
+
+/// Distinct counts of tokens per group, e.g., distinct users per URL, with
+/// a memory limit shared by all groups.
+///
+/// Each group keeps a sample of its tokens as `CountUnique` does, with
+/// priorities that are hashes of the tokens as for `Hashed`. Groups are
+/// created when their first token is added, and are exact until they lose a
+/// token. Whenever the groups together exceed the budget, the group with the
+/// largest sample discards its token with the largest priority and lowers its
+/// sampling probability. Small groups thus stay exact, and the budget is
+/// shared evenly among the largest groups.
+///
+/// Every group keeps at least one token. Once no group has more than one, the
+/// group with the smallest estimate is dropped entirely to make room, so the
+/// number of groups is bounded by the budget as well. `estimate()` returns
+/// `None` for dropped groups, as for groups that never had a token. Dropped
+/// keys are not remembered, so once any group has been dropped, a group that
+/// is created afterwards may have lost tokens when it was dropped before, and
+/// is never exact.
+///
+/// # Example
+/// ```
+/// use count_unique_cvm::*;
+/// use std::collections::hash_map::RandomState;
+///
+/// let mut c = GroupedCountUnique::new(RandomState::new(), 1000);
+/// for user in 0..10_000u64 {
+///     c.add_token("/index.html", user);
+///     c.add_token("/about.html", user % 10);
+/// }
+/// assert!(c.estimate(&"/about.html") == Some(10.0));
+/// println!("Top URLs: {:?}", c.top(1));
+/// ```
+pub struct GroupedCountUnique<K, T, S>
+where
+    K: Ord + Clone,
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    groups: BTreeMap<K, Group<T>>,
+    // Groups by the number of sampled tokens, to find the largest, and then
+    // by decreasing sampling probability, to find the smallest estimate.
+    by_size: BTreeSet<(usize, Reverse<u64>, K)>,
+    priorities: Hashed<S>,
+    // The budget, and the cost of each sampled token and each group.
+    budget: usize,
+    sample_cost: usize,
+    group_cost: usize,
+    samples: usize,
+    // Whether a group has been dropped since the last reset.
+    dropped: bool,
+}
+
+impl<K, T, S> GroupedCountUnique<K, T, S>
+where
+    K: Ord + Clone,
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    /// Create a new `GroupedCountUnique` whose groups together store at most
+    /// `max_samples` tokens, with priorities computed by `hasher`. Since every
+    /// group stores a token, there are at most `max_samples` groups.
+    ///
+    /// # Panics
+    /// Function will panic if called with `max_samples < 1`.
+    pub fn new(hasher: S, max_samples: usize) -> Self {
+        Self::with_costs(hasher, max_samples, 1, 0)
+    }
+
+    /// Create a new `GroupedCountUnique` whose groups and samples fit in
+    /// `bytes` bytes. Each group is charged for its key and its entries in
+    /// the maps of groups, and each sampled token as for
+    /// `CountUnique::with_memory_budget()`. Heap memory owned by keys or
+    /// tokens, and the maps' internal nodes, are not counted.
+    ///
+    /// # Panics
+    /// Function will panic if `bytes` is too small to hold one group with
+    /// one token.
+    pub fn with_memory_budget(hasher: S, bytes: usize) -> Self {
+        let group_cost =
+            mem::size_of::<(K, Group<T>)>() + mem::size_of::<(usize, Reverse<u64>, K)>();
+        Self::with_costs(hasher, bytes, Treap::<T, f64>::node_size(), group_cost)
+    }
+
+    fn with_costs(hasher: S, budget: usize, sample_cost: usize, group_cost: usize) -> Self {
+        if budget < sample_cost + group_cost {
+            panic!("Cannot count without state");
+        }
+        GroupedCountUnique {
+            groups: BTreeMap::new(),
+            by_size: BTreeSet::new(),
+            priorities: Hashed(hasher),
+            budget,
+            sample_cost,
+            group_cost,
+            samples: 0,
+            dropped: false,
+        }
+    }
+
+    /// Add token `t` to group `k`.
+    pub fn add_token(&mut self, k: K, t: T) {
+        let u = self.priorities.priority(&t);
+        let complete = !self.dropped;
+        let g = self.groups.entry(k.clone()).or_insert_with(|| Group {
+            treap: Treap::new(),
+            p: 1.0,
+            complete,
+        });
+        // Priorities are deterministic, so the token is either already
+        // sampled or cannot be.
+        if u >= g.p || g.treap.contains(&t) {
+            return;
+        }
+        let size = g.treap.size();
+        let p = g.p;
+        g.treap.insert(Element::new(t, u));
+        if size > 0 {
+            self.by_size
+                .remove(&(size, Reverse(p.to_bits()), k.clone()));
+        }
+        self.by_size.insert((size + 1, Reverse(p.to_bits()), k));
+        self.samples += 1;
+        while self.cost() > self.budget {
+            self.evict();
+        }
+    }
+
+    /// Return the part of the budget in use.
+    fn cost(&self) -> usize {
+        self.samples * self.sample_cost + self.groups.len() * self.group_cost
+    }
+
+    /// Discard the token with the largest priority from the largest group or,
+    /// if every group has a single token, drop the group with the smallest
+    /// estimate.
+    fn evict(&mut self) {
+        // These unwraps are safe: every group has at least one sampled token.
+        let (size, _, _) = self.by_size.last().unwrap();
+        if *size == 1 {
+            let (_, _, k) = self.by_size.pop_first().unwrap();
+            self.groups.remove(&k);
+            self.samples -= 1;
+            self.dropped = true;
+            return;
+        }
+        let (size, _, k) = self.by_size.pop_last().unwrap();
+        let g = self.groups.get_mut(&k).unwrap();
+        let (m_priority, m_value) = {
+            let m = g.treap.get_max().unwrap();
+            (*m.priority(), m.value().clone())
+        };
+        g.treap.delete(&m_value);
+        g.p = m_priority;
+        self.by_size.insert((size - 1, Reverse(g.p.to_bits()), k));
+        self.samples -= 1;
+    }
+
+    /// Return the estimated number of distinct tokens in group `k`, or `None`
+    /// if the group is not tracked: no token was added to it, or it was
+    /// dropped to stay within the budget.
+    pub fn estimate(&self, k: &K) -> Option<f64> {
+        self.groups.get(k).map(|g| g.treap.size() as f64 / g.p)
+    }
+
+    /// Check whether group `k` is tracked and its estimate is exact. A group
+    /// created after another group was dropped is never exact.
+    pub fn is_exact(&self, k: &K) -> bool {
+        self.groups.get(k).is_some_and(|g| g.complete && g.p >= 1.0)
+    }
+
+    /// Return the groups and their estimated numbers of distinct tokens, in
+    /// order of the groups.
+    pub fn estimates(&self) -> impl Iterator<Item = (&K, f64)> + '_ {
+        self.groups
+            .iter()
+            .map(|(k, g)| (k, g.treap.size() as f64 / g.p))
+    }
+
+    /// Return the `n` groups with the largest estimated numbers of distinct
+    /// tokens, largest first.
+    pub fn top(&self, n: usize) -> Vec<(&K, f64)> {
+        let mut all: Vec<(&K, f64)> = self.estimates().collect();
+        // Only sort the `n` largest.
+        if n < all.len() {
+            all.select_nth_unstable_by(n, |a, b| b.1.total_cmp(&a.1));
+            all.truncate(n);
+        }
+        all.sort_by(|a, b| b.1.total_cmp(&a.1));
+        all
+    }
+
+    /// Return the number of groups.
+    pub fn groups(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Return the number of tokens sampled by all groups.
+    pub fn samples(&self) -> usize {
+        self.samples
+    }
+
+    /// Reset the state, removing all groups.
+    pub fn reset(&mut self) {
+        self.groups.clear();
+        self.by_size.clear();
+        self.samples = 0;
+        self.dropped = false;
+    }
+}
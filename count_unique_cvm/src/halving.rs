@@ -0,0 +1,169 @@
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use conv::*;
+use rand::Rng;
+
+use crate::{threshold, DistinctCounter};
+
+/// Errors returned by `HalvingCountUnique::estimate()`.
+#[derive(Debug)]
+pub enum HalvingError {
+    /// The algorithm failed (output ⊥): halving the buffer did not remove
+    /// any element.
+    Failed,
+    /// The buffer size could not be converted to a float.
+    Overflow(PosOverflow<usize>),
+}
+
+impl Display for HalvingError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HalvingError::Failed => write!(f, "halving the buffer did not remove any element"),
+            HalvingError::Overflow(_) => write!(f, "buffer size cannot be converted to a float"),
+        }
+    }
+}
+
+impl Error for HalvingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            HalvingError::Failed => None,
+            HalvingError::Overflow(e) => Some(e),
+        }
+    }
+}
+
+/// The original CVM algorithm from Chakraborty, Vinodchandran and Meel,
+/// "Distinct Elements in Streams: An Algorithm for the (Text) Book" (ESA 2022).
+///
+/// Each token is kept with probability `p`. When the buffer reaches the
+/// threshold, every element is discarded with probability 1/2 and `p` is
+/// halved. If no element was discarded, the algorithm fails and
+/// `estimate()` returns `HalvingError::Failed` until `reset()` is called.
+///
+/// # Example
+/// ```
+/// use count_unique_cvm::*;
+///
+/// let mut c = HalvingCountUnique::with_accuracy(rand::thread_rng(), 0.5, 0.1, 100);
+/// for i in 0..100 {
+///     c.add_token(i);
+/// }
+/// println!("Estimated number of tokens are {}", c.estimate().unwrap());
+/// ```
+pub struct HalvingCountUnique<T: Ord, R: Rng> {
+    buffer: BTreeSet<T>,
+    rng: R,
+    thresh: usize,
+    p: f64,
+    failed: bool,
+    guarantee: Option<(f64, f64)>,
+}
+
+impl<T, R> HalvingCountUnique<T, R>
+where
+    T: Ord,
+    R: Rng,
+{
+    /// Create a new `HalvingCountUnique` that halves its buffer when it
+    /// holds `thresh` elements.
+    ///
+    /// # Panics
+    /// Function will panic if called with `thresh < 1`.
+    pub fn new(r: R, thresh: usize) -> Self {
+        if thresh < 1 {
+            panic!("Cannot count without state");
+        }
+        HalvingCountUnique {
+            buffer: BTreeSet::new(),
+            rng: r,
+            thresh,
+            p: 1.0f64,
+            failed: false,
+            guarantee: None,
+        }
+    }
+
+    /// Create a new `HalvingCountUnique` whose estimate is within a factor
+    /// of `(1 ± epsilon)` of the true count with probability at least
+    /// `1 - delta`, for streams of at most `stream_len` tokens. The threshold
+    /// is computed by `threshold()`.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 < epsilon`, `0 < delta < 1` and
+    /// `stream_len > 0`.
+    pub fn with_accuracy(r: R, epsilon: f64, delta: f64, stream_len: u64) -> Self {
+        let mut c = Self::new(r, threshold(epsilon, delta, stream_len));
+        c.guarantee = Some((epsilon, delta));
+        c
+    }
+
+    /// Return the `(epsilon, delta)` guarantee this structure was configured
+    /// for by `with_accuracy()`, or `None` if it was created with `new()`.
+    pub fn guarantee(&self) -> Option<(f64, f64)> {
+        self.guarantee
+    }
+
+    /// Add a token to the CVM.
+    pub fn add_token(&mut self, t: T) {
+        if self.failed {
+            return;
+        }
+        self.buffer.remove(&t);
+        if self.rng.gen::<f64>() < self.p {
+            self.buffer.insert(t);
+        }
+        if self.buffer.len() == self.thresh {
+            let rng = &mut self.rng;
+            self.buffer.retain(|_| rng.gen::<bool>());
+            self.p /= 2.0;
+            if self.buffer.len() == self.thresh {
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Return the current estimated number of tokens, or
+    /// `HalvingError::Failed` if the algorithm failed.
+    pub fn estimate(&self) -> Result<f64, HalvingError> {
+        if self.failed {
+            return Err(HalvingError::Failed);
+        }
+        let f = f64::value_from(self.buffer.len()).map_err(HalvingError::Overflow)?;
+        Ok(f / self.p)
+    }
+
+    /// Check whether the algorithm failed.
+    pub fn is_failed(&self) -> bool {
+        self.failed
+    }
+
+    /// Reset the CVM state allowing the structure to be reused.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.p = 1.0f64;
+        self.failed = false;
+    }
+}
+
+impl<T, R> DistinctCounter<T> for HalvingCountUnique<T, R>
+where
+    T: Ord,
+    R: Rng,
+{
+    type Error = HalvingError;
+
+    fn add_token(&mut self, t: T) {
+        HalvingCountUnique::add_token(self, t)
+    }
+
+    fn estimate(&self) -> Result<f64, HalvingError> {
+        HalvingCountUnique::estimate(self)
+    }
+
+    fn reset(&mut self) {
+        HalvingCountUnique::reset(self)
+    }
+}
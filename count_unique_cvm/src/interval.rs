@@ -0,0 +1,35 @@
+//! Confidence intervals for CVM estimates.
+//!
+//! Given the sampling probability `p`, each distinct token is in the buffer
+//! independently with probability `p`, so the number `k` of sampled tokens is
+//! binomially distributed with mean `n·p`, where `n` is the number of
+//! distinct tokens. We invert the multiplicative Chernoff bounds
+//!
+//! * Pr[k ≥ (1 + ε)·μ] ≤ exp(-ε²μ / (2 + ε))
+//! * Pr[k ≤ (1 - ε)·μ] ≤ exp(-ε²μ / 2)
+//!
+//! to find the smallest and largest `μ = n·p` for which the observed `k`
+//! is not in either tail. The resulting intervals are conservative.
+
+/// Return `(low, high)` bounds on `n` given `k` sampled tokens at sampling
+/// probability `p`, holding with probability at least `confidence`.
+///
+/// # Panics
+/// Function will panic unless `0 < confidence < 1`.
+pub(crate) fn chernoff_interval(k: f64, p: f64, confidence: f64) -> (f64, f64) {
+    if !(confidence > 0.0 && confidence < 1.0) {
+        panic!("Confidence must be between 0 and 1");
+    }
+    if p >= 1.0 {
+        // Nothing has been discarded, the count is exact.
+        return (k, k);
+    }
+    // Each tail gets half of the allowed failure probability.
+    let l = (2.0 / (1.0 - confidence)).ln();
+    // Solve (k - μ)² = l·(μ + k) for μ < k.
+    let low = ((2.0 * k + l) - (8.0 * k * l + l * l).sqrt()) / 2.0;
+    // Solve (μ - k)² = 2·l·μ for μ > k.
+    let high = k + l + (2.0 * k * l + l * l).sqrt();
+    // The `k` sampled tokens are distinct, so there are at least `k`.
+    ((low / p).max(k), high / p)
+}
@@ -1,7 +1,16 @@
 //! An implementation of the CVM algorithm
 //! The [CVM]((https://cs.stanford.edu/~knuth/papers/cvm-note.pdf) algorithm provides
 //! a probabilistic data structure for counting the number of unique elements in a stream.
-//! This is an implementation of the simple version of the algorithm.
+//! `CountUnique` implements the simple version of the algorithm from Knuth's note, which
+//! evicts the sampled token with the largest priority when the buffer is full.
+//! `HalvingCountUnique` implements the original algorithm by Chakraborty, Vinodchandran
+//! and Meel, which halves the buffer instead. Both implement the `DistinctCounter` trait.
+//! `BoostedCountUnique` runs several independent sketches and returns the median of
+//! their group means, which makes large errors exponentially unlikely.
+//! `WindowedCountUnique` estimates the number of distinct tokens in a sliding window.
+//! `GroupedCountUnique` counts distinct tokens per group with a shared memory budget.
+//! `FingerprintCountUnique` stores 64-bit hashes of tokens instead of the tokens, so
+//! that its memory use is known up front.
 //!
 //! # Example
 //! ```
@@ -16,28 +25,125 @@
 //! // The actual value depends on the random number generator,
 //! // but should be close.
 //! println!("Estimated number of tokens are {}", c.estimate().unwrap());
+//!
+//! // Alternately, size the CVM so that the estimate is within 10% of the
+//! // actual value with probability 0.99, for streams of up to 1M tokens.
+//! let c = CountUnique::<u64, _>::with_accuracy(rand::thread_rng(), 0.1, 0.01, 1_000_000);
+//! assert_eq!(c.guarantee(), Some((0.1, 0.01)));
 //! ```
+//!
+//! # Serialization
+//! With the `serde` feature, `CountUnique::checkpoint()` saves the state of a
+//! sketch as a `Checkpoint`, which can be encoded with serde or with
+//! `Checkpoint::to_bytes()`, and `CountUnique::restore()` rebuilds it.
+//! `CountUnique` is itself serializable when its random number generator is.
 #![deny(missing_docs)]
 
+mod boosted;
+#[cfg(feature = "serde")]
+mod checkpoint;
+mod fingerprint;
+mod grouped;
+mod halving;
+mod interval;
+mod priority;
+mod window;
+pub use boosted::{groups_for, BoostedCountUnique};
+#[cfg(feature = "serde")]
+pub use checkpoint::{Checkpoint, CheckpointError, CHECKPOINT_VERSION};
+pub use fingerprint::FingerprintCountUnique;
+pub use grouped::GroupedCountUnique;
+pub use halving::{HalvingCountUnique, HalvingError};
+pub use priority::{Hashed, PrioritySource};
+pub use window::{Window, WindowedCountUnique};
+
 use conv::*;
-use rand::Rng;
+use interval::chernoff_interval;
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::hash::{BuildHasher, Hash};
 use treap::{Element, Treap};
 use treap_non_random as treap;
 
+/// A streaming estimator for the number of distinct tokens.
+pub trait DistinctCounter<T> {
+    /// The error returned when no estimate is available.
+    type Error;
+
+    /// Add a token to the stream.
+    fn add_token(&mut self, t: T);
+
+    /// Return the current estimated number of distinct tokens.
+    fn estimate(&self) -> Result<f64, Self::Error>;
+
+    /// Reset the state allowing the structure to be reused.
+    fn reset(&mut self);
+}
+
+/// The number of distinct tokens reported by `CountUnique::cardinality()`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cardinality {
+    /// No token has been discarded, so this is the exact count.
+    Exact(usize),
+    /// Tokens have been discarded, so the count is estimated from a sample.
+    Approximate {
+        /// The estimated number of distinct tokens.
+        estimate: f64,
+        /// The probability with which each distinct token was sampled.
+        sampling_probability: f64,
+    },
+}
+
+impl Cardinality {
+    /// Return the count, or the estimate if it is approximate.
+    pub fn value(&self) -> f64 {
+        match self {
+            Cardinality::Exact(n) => *n as f64,
+            Cardinality::Approximate { estimate, .. } => *estimate,
+        }
+    }
+
+    /// Check whether the count is exact.
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Cardinality::Exact(_))
+    }
+}
+
+/// Compute the buffer size from the CVM paper that guarantees an estimate
+/// within a factor of `(1 ± epsilon)` of the true count with probability at
+/// least `1 - delta`, for a stream of at most `stream_len` tokens:
+/// ⌈12/ε² · log₂(8m/δ)⌉.
+///
+/// # Panics
+/// Function will panic unless `0 < epsilon`, `0 < delta < 1` and
+/// `stream_len > 0`.
+pub fn threshold(epsilon: f64, delta: f64, stream_len: u64) -> usize {
+    if !(epsilon > 0.0 && delta > 0.0 && delta < 1.0 && stream_len > 0) {
+        panic!("Invalid accuracy parameters");
+    }
+    let m = stream_len as f64;
+    (12.0 / (epsilon * epsilon) * (8.0 * m / delta).log2()).ceil() as usize
+}
+
 /// The CVM algorithm state. `T` is the type of tokens that are
 /// being counted, and `R` is the random number generator that should
-/// be used.
-pub struct CountUnique<T: Ord + Clone, R: Rng> {
-    treap: Treap<T, f32>,
+/// be used. `R` can also be any other `PrioritySource`, e.g., `Hashed`.
+///
+/// `CountUnique` implements `Extend` but not `FromIterator`, since a sketch
+/// needs a priority source and a buffer size, which `from_iter()` cannot
+/// take. Create it with `new()` and then call `extend()` or `add_tokens()`.
+pub struct CountUnique<T: Ord + Clone, R: PrioritySource<T>> {
+    treap: Treap<T, f64>,
     rng: R,
     max_size: usize,
-    p: f32,
+    p: f64,
+    guarantee: Option<(f64, f64)>,
 }
 
 impl<T, R> CountUnique<T, R>
 where
     T: Ord + Clone,
-    R: Rng,
+    R: PrioritySource<T>,
 {
     /// Create a new `CountUnique` structure. `sz` is the number
     /// of elements stored by CVM, and must be non-zero.
@@ -52,31 +158,129 @@ where
             treap: Treap::new(),
             rng: r,
             max_size: sz,
-            p: 1.0f32,
+            p: 1.0f64,
+            guarantee: None,
         }
     }
 
+    /// Create a new `CountUnique` structure that stores as many elements as
+    /// fit in `bytes` bytes. Only the memory allocated for each element is
+    /// counted: tokens that own heap memory, e.g., `String`s, use more. Use
+    /// `FingerprintCountUnique` to bound the memory used for such tokens.
+    ///
+    /// # Panics
+    /// Function will panic if `bytes` is too small to hold one element.
+    pub fn with_memory_budget(r: R, bytes: usize) -> Self {
+        Self::new(r, bytes / Treap::<T, f64>::node_size())
+    }
+
+    /// Create a new `CountUnique` structure whose estimate is within a
+    /// factor of `(1 ± epsilon)` of the true count with probability at least
+    /// `1 - delta`, for streams of at most `max_stream_len` tokens. The
+    /// number of elements stored is computed by `threshold()`.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 < epsilon`, `0 < delta < 1` and
+    /// `max_stream_len > 0`.
+    pub fn with_accuracy(r: R, epsilon: f64, delta: f64, max_stream_len: u64) -> Self {
+        let mut c = Self::new(r, threshold(epsilon, delta, max_stream_len));
+        c.guarantee = Some((epsilon, delta));
+        c
+    }
+
+    /// Return the `(epsilon, delta)` guarantee this structure was configured
+    /// for by `with_accuracy()`, or `None` if it was created with `new()`.
+    pub fn guarantee(&self) -> Option<(f64, f64)> {
+        self.guarantee
+    }
+
+    /// Return the number of elements stored by CVM.
+    pub fn buffer_size(&self) -> usize {
+        self.max_size
+    }
+
+    /// Return the largest number of bytes allocated for stored elements, not
+    /// counting heap memory owned by the tokens.
+    pub fn memory_bound(&self) -> usize {
+        self.max_size * Treap::<T, f64>::node_size()
+    }
+
     /// Add a token to the CVM.
     pub fn add_token(&mut self, t: T) {
-        let u = self.rng.gen::<f32>();
-        self.treap.delete(&t);
-        if u < self.p {
-            if self.treap.size() < self.max_size {
-                self.treap.insert(Element::new(t, u));
-            } else {
-                // This unwrap is safe: we are guaranteed to have one.
-                let (m_priority, m_value) = {
-                    let m = self.treap.get_max().unwrap();
-                    (*m.priority(), m.value().clone())
-                };
-                if m_priority > u {
-                    self.p = u;
-                } else {
-                    self.treap.delete(&m_value);
-                    self.treap.insert(Element::new(t, u));
-                    self.p = m_priority;
-                }
+        let u = self.rng.priority(&t);
+        self.add_with_priority(t, u);
+    }
+
+    /// Add every token from `tokens` to the CVM, in order. Equivalent to
+    /// calling `add_token()` for each; use `with_hasher()` for throughput.
+    pub fn add_tokens<I>(&mut self, tokens: I)
+    where
+        I: IntoIterator<Item = T>,
+    {
+        for t in tokens {
+            self.add_token(t);
+        }
+    }
+
+    /// Add a token to the CVM without taking ownership of it. The token is
+    /// only converted with `to_owned()` if it is sampled, so counting, e.g.,
+    /// `&str` slices for a `CountUnique<String, _>` does not allocate for
+    /// discarded tokens.
+    pub fn add_token_ref<Q>(&mut self, t: &Q)
+    where
+        T: Borrow<Q>,
+        Q: Ord + ToOwned<Owned = T> + ?Sized,
+        R: PrioritySource<Q>,
+    {
+        let u = self.rng.priority(t);
+        if self.make_room(t, u) {
+            self.treap.insert(Element::new(t.to_owned(), u));
+        }
+    }
+
+    fn add_with_priority(&mut self, t: T, u: f64) {
+        if self.make_room(&t, u) {
+            self.treap.insert(Element::new(t, u));
+        }
+    }
+
+    /// Prepare to add token `t` with priority `u`, and return whether it
+    /// should be inserted.
+    fn make_room<Q>(&mut self, t: &Q, u: f64) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        if R::DETERMINISTIC {
+            // The token's priority never changes, so it is either already
+            // sampled or cannot be.
+            if u >= self.p || self.treap.contains_by(t) {
+                return false;
             }
+        } else {
+            self.treap.delete_by(t);
+        }
+        if u >= self.p {
+            return false;
+        }
+        if self.treap.size() < self.max_size {
+            return true;
+        }
+        // This unwrap is safe: we are guaranteed to have one.
+        let (m_priority, m_value) = {
+            let m = self.treap.get_max().unwrap();
+            (*m.priority(), m.value().clone())
+        };
+        // Keep the s tokens with the smallest priorities: either the new
+        // token is not among them, or it replaces the current maximum.
+        // Either way p becomes the smallest priority seen that is not kept.
+        if u > m_priority {
+            self.p = u;
+            false
+        } else {
+            self.treap.delete(&m_value);
+            self.p = m_priority;
+            true
         }
     }
 
@@ -86,14 +290,352 @@ where
     /// that it will return the correct value in expectation.
     pub fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
         let f = f64::value_from(self.treap.size())?;
-        let p = self.p as f64;
-        Ok(f / p)
+        Ok(f / self.p)
+    }
+
+    /// Check whether the sketch is exact, i.e., no token has been discarded
+    /// and `estimate()` returns the number of distinct tokens.
+    pub fn is_exact(&self) -> bool {
+        self.p >= 1.0
+    }
+
+    /// Return `Cardinality::Exact` with the number of distinct tokens while
+    /// the sketch is exact, and `Cardinality::Approximate` with the value of
+    /// `estimate()` afterwards.
+    pub fn cardinality(&self) -> Result<Cardinality, PosOverflow<usize>> {
+        if self.is_exact() {
+            Ok(Cardinality::Exact(self.treap.size()))
+        } else {
+            Ok(Cardinality::Approximate {
+                estimate: self.estimate()?,
+                sampling_probability: self.p,
+            })
+        }
+    }
+
+    /// Return `(low, estimate, high)`, where `estimate` is the value returned
+    /// by `estimate()` and the actual number of tokens lies between `low` and
+    /// `high` with probability at least `confidence`. The interval is computed
+    /// from Chernoff bounds on the number of sampled tokens given the current
+    /// sampling probability, and is conservative. While no token has been
+    /// discarded the count is exact and `low == estimate == high`.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 < confidence < 1`.
+    pub fn estimate_with_interval(
+        &self,
+        confidence: f64,
+    ) -> Result<(f64, f64, f64), PosOverflow<usize>> {
+        let k = f64::value_from(self.treap.size())?;
+        let (low, high) = chernoff_interval(k, self.p, confidence);
+        Ok((low, k / self.p, high))
+    }
+
+    /// Estimate the number of distinct tokens for which `pred` returns true,
+    /// e.g., the number of distinct users from one country in a sketch of all
+    /// users. Returns `(low, estimate, high)` as `estimate_with_interval()`
+    /// does, where `estimate` is the number of matching sampled tokens
+    /// divided by the sampling probability. Subsets with few distinct tokens
+    /// have few samples, so their intervals are wide.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 < confidence < 1`.
+    pub fn estimate_where<F>(
+        &self,
+        mut pred: F,
+        confidence: f64,
+    ) -> Result<(f64, f64, f64), PosOverflow<usize>>
+    where
+        F: FnMut(&T) -> bool,
+    {
+        let k = f64::value_from(self.sample().filter(|t| pred(t)).count())?;
+        let (low, high) = chernoff_interval(k, self.p, confidence);
+        Ok((low, k / self.p, high))
+    }
+
+    /// Estimate the sum of `f` over the distinct tokens in the stream, e.g.,
+    /// the total balance of distinct active accounts. Returns
+    /// `(estimate, variance)`, where `estimate` is the Horvitz–Thompson
+    /// estimator, the sum of `f` over sampled tokens divided by the sampling
+    /// probability `p`, and `variance` is an unbiased estimate of its
+    /// variance, `(1 - p)/p²` times the sum of `f²` over sampled tokens.
+    pub fn estimate_sum<F>(&self, mut f: F) -> (f64, f64)
+    where
+        F: FnMut(&T) -> f64,
+    {
+        let (sum, sum_sq) = self.sample().fold((0.0, 0.0), |(s, s2), t| {
+            let x = f(t);
+            (s + x, s2 + x * x)
+        });
+        (sum / self.p, (1.0 - self.p) / (self.p * self.p) * sum_sq)
+    }
+
+    /// Estimate the mean of `f` over the distinct tokens in the stream.
+    /// Returns `(estimate, variance)`, where `estimate` is the mean of `f`
+    /// over sampled tokens, i.e., the ratio of the Horvitz–Thompson estimates
+    /// of the sum and of the count, and `variance` is its linearized
+    /// (Taylor series) variance estimate. The estimate is consistent but
+    /// slightly biased for small samples. Returns `None` if no token is
+    /// sampled.
+    pub fn estimate_mean<F>(&self, mut f: F) -> Option<(f64, f64)>
+    where
+        F: FnMut(&T) -> f64,
+    {
+        let values: Vec<f64> = self.sample().map(&mut f).collect();
+        if values.is_empty() {
+            return None;
+        }
+        let k = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / k;
+        let ss = values.iter().map(|x| (x - mean).powi(2)).sum::<f64>();
+        Some((mean, (1.0 - self.p) * ss / (k * k)))
+    }
+
+    /// Return an estimate of the `q`-quantile of the distinct tokens, e.g.,
+    /// the median of distinct request sizes when `q` is 0.5. Repeated tokens
+    /// count once. Returns the smallest sampled token such that at least a
+    /// `q` fraction of the sample is no larger, or `None` if no token is
+    /// sampled. See `quantile_error()` for the accuracy.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 <= q <= 1`.
+    pub fn quantile(&self, q: f64) -> Option<&T> {
+        if !(0.0..=1.0).contains(&q) {
+            panic!("Quantile must be between 0 and 1");
+        }
+        let k = self.treap.size();
+        let rank = ((q * k as f64).ceil() as usize).clamp(1, k.max(1));
+        self.sample().nth(rank - 1)
+    }
+
+    /// Return an estimate of the fraction of distinct tokens that are at most
+    /// `t`, or `None` if no token is sampled. See `quantile_error()` for the
+    /// accuracy.
+    pub fn cdf(&self, t: &T) -> Option<f64> {
+        let k = self.treap.size();
+        if k == 0 {
+            return None;
+        }
+        let below = self.sample().take_while(|s| *s <= t).count();
+        Some(below as f64 / k as f64)
+    }
+
+    /// Return the largest error of `cdf()` over all tokens, holding with
+    /// probability at least `confidence`. This is the Dvoretzky–Kiefer–Wolfowitz
+    /// bound `sqrt(ln(2/(1 - confidence)) / 2k)` for a sample of `k` tokens.
+    /// The rank of the token returned by `quantile(q)` among the distinct
+    /// tokens is within the same fraction of `q`. Returns 0 while no token
+    /// has been discarded, and `None` if no token is sampled.
+    ///
+    /// # Panics
+    /// Function will panic unless `0 < confidence < 1`.
+    pub fn quantile_error(&self, confidence: f64) -> Option<f64> {
+        if !(confidence > 0.0 && confidence < 1.0) {
+            panic!("Confidence must be between 0 and 1");
+        }
+        let k = self.treap.size();
+        if k == 0 {
+            None
+        } else if self.p >= 1.0 {
+            Some(0.0)
+        } else {
+            Some(((2.0 / (1.0 - confidence)).ln() / (2.0 * k as f64)).sqrt())
+        }
+    }
+
+    /// Return the sampled tokens in increasing order. Each distinct token in
+    /// the stream is in the sample with probability `sampling_probability()`,
+    /// so the sample is a uniform random sample of the distinct tokens.
+    pub fn sample(&self) -> impl Iterator<Item = &T> + '_ {
+        self.treap.iter().map(|e| e.value())
+    }
+
+    /// Return the probability with which each distinct token is sampled. This
+    /// is 1 until a token has been discarded.
+    pub fn sampling_probability(&self) -> f64 {
+        self.p
     }
 
     /// Reset the CVM state allowing the structure to be reused.
     pub fn reset(&mut self) {
         self.treap.reset();
-        self.p = 1.0f32;
+        self.p = 1.0f64;
+    }
+
+    /// Merge `other` into `self`, so that `self` estimates the number of
+    /// distinct tokens in the union of both streams. The merged sketch keeps
+    /// `self`'s buffer size, and its sampling probability is the smaller of
+    /// the two, lowered further if the combined samples do not fit.
+    ///
+    /// With random priorities the streams must be disjoint, e.g., sharded by
+    /// token. Every occurrence of a token draws a fresh priority, so a token
+    /// seen by both streams has two independent priorities and is more likely
+    /// to be sampled than a token seen by one stream, which biases the
+    /// estimate for overlapping streams upwards. With `Hashed` priorities the
+    /// streams may overlap, and the merged sketch is the one that adding every
+    /// token to `self` would produce, provided both use the same hasher.
+    pub fn merge(&mut self, other: &Self) {
+        let mut p = self.p.min(other.p);
+        // Both iterators are sorted by token, merge them and drop duplicates,
+        // keeping the smaller priority. Hashed duplicates have equal priorities.
+        let mut samples: Vec<(T, f64)> = Vec::with_capacity(self.treap.size() + other.treap.size());
+        let mut a = self.treap.iter().peekable();
+        let mut b = other.treap.iter().peekable();
+        loop {
+            let e = match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(_), None) => a.next().unwrap(),
+                (None, Some(_)) => b.next().unwrap(),
+                (Some(x), Some(y)) => match x.value().cmp(y.value()) {
+                    Ordering::Less => a.next().unwrap(),
+                    Ordering::Greater => b.next().unwrap(),
+                    Ordering::Equal => {
+                        let (x, y) = (a.next().unwrap(), b.next().unwrap());
+                        if x.priority() <= y.priority() {
+                            x
+                        } else {
+                            y
+                        }
+                    }
+                },
+            };
+            if *e.priority() < p {
+                samples.push((e.value().clone(), *e.priority()));
+            }
+        }
+        if samples.len() > self.max_size {
+            // Keep the `max_size` smallest priorities; p becomes the
+            // smallest priority that was discarded.
+            samples.select_nth_unstable_by(self.max_size, |x, y| x.1.total_cmp(&y.1));
+            p = samples[self.max_size].1;
+            samples.truncate(self.max_size);
+        }
+        self.treap.reset();
+        for (t, u) in samples {
+            self.treap.insert(Element::new(t, u));
+        }
+        self.p = p;
+    }
+
+    /// Get statistics about operations on the underlying treap. These
+    /// are not cleared by `reset()`.
+    #[cfg(feature = "stats")]
+    pub fn treap_stats(&self) -> treap::Stats {
+        self.treap.stats()
+    }
+
+    /// Get the current height of the underlying treap.
+    pub fn treap_height(&self) -> usize {
+        self.treap.height()
+    }
+
+    /// Get the current average depth of elements in the underlying treap.
+    pub fn treap_average_depth(&self) -> Option<f64> {
+        self.treap.average_depth()
+    }
+}
+
+impl<T, S> CountUnique<T, Hashed<S>>
+where
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    /// Create a new `CountUnique` structure whose priorities are hashes of
+    /// tokens computed by `hasher`. `sz` is the number of elements stored by
+    /// CVM, and must be non-zero. See `Hashed` for details.
+    ///
+    /// # Panics
+    /// Function will panic if called with `sz < 1`.
+    pub fn with_hasher(hasher: S, sz: usize) -> Self {
+        Self::new(Hashed(hasher), sz)
+    }
+
+    /// Estimate the number of distinct tokens in the union of the streams
+    /// added to `self` and `other`. Both sketches must use the same hasher.
+    pub fn union_estimate(&self, other: &Self) -> Result<f64, PosOverflow<usize>> {
+        let (union, _, p) = self.overlap(other);
+        Ok(f64::value_from(union)? / p)
+    }
+
+    /// Estimate the number of distinct tokens added to both `self` and
+    /// `other`, e.g., the users active on both of two days. Both sketches
+    /// must use the same hasher.
+    pub fn intersection_estimate(&self, other: &Self) -> Result<f64, PosOverflow<usize>> {
+        let (_, both, p) = self.overlap(other);
+        Ok(f64::value_from(both)? / p)
+    }
+
+    /// Estimate the Jaccard similarity |A∩B| / |A∪B| of the sets of distinct
+    /// tokens added to `self` and `other`, or `None` if neither sketch
+    /// samples a token. Both sketches must use the same hasher.
+    pub fn jaccard(&self, other: &Self) -> Option<f64> {
+        let (union, both, _) = self.overlap(other);
+        if union == 0 {
+            None
+        } else {
+            Some(both as f64 / union as f64)
+        }
+    }
+
+    /// Compare the samples at the smaller of the two sampling probabilities
+    /// `p`. With hashed priorities each sample holds exactly the tokens whose
+    /// priority is below its sampling probability, so restricting both to
+    /// priorities below `p` gives the tokens of each stream hashed below `p`.
+    /// Returns the number of such tokens in either and in both streams, and
+    /// `p`.
+    fn overlap(&self, other: &Self) -> (usize, usize, f64) {
+        let p = self.p.min(other.p);
+        let mut a = self.treap.iter().filter(|e| *e.priority() < p).peekable();
+        let mut b = other.treap.iter().filter(|e| *e.priority() < p).peekable();
+        let (mut union, mut both) = (0, 0);
+        loop {
+            match (a.peek(), b.peek()) {
+                (None, None) => break,
+                (Some(_), None) => _ = a.next(),
+                (None, Some(_)) => _ = b.next(),
+                (Some(x), Some(y)) => match x.value().cmp(y.value()) {
+                    Ordering::Less => _ = a.next(),
+                    Ordering::Greater => _ = b.next(),
+                    Ordering::Equal => {
+                        a.next();
+                        b.next();
+                        both += 1;
+                    }
+                },
+            }
+            union += 1;
+        }
+        (union, both, p)
+    }
+}
+
+impl<T, R> Extend<T> for CountUnique<T, R>
+where
+    T: Ord + Clone,
+    R: PrioritySource<T>,
+{
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        self.add_tokens(iter)
+    }
+}
+
+impl<T, R> DistinctCounter<T> for CountUnique<T, R>
+where
+    T: Ord + Clone,
+    R: PrioritySource<T>,
+{
+    type Error = PosOverflow<usize>;
+
+    fn add_token(&mut self, t: T) {
+        CountUnique::add_token(self, t)
+    }
+
+    fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
+        CountUnique::estimate(self)
+    }
+
+    fn reset(&mut self) {
+        CountUnique::reset(self)
     }
 }
 
@@ -133,4 +675,803 @@ mod test {
         println!("Average {}", average);
         assert!(0.0 < average);
     }
+
+    /// Run `trials` independent estimates, each over a stream containing
+    /// `distinct` tokens that each appear `repeats` times in random order.
+    fn run_trials(sz: usize, distinct: u64, repeats: usize, trials: usize) -> Vec<f64> {
+        let ctr = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), sz);
+        run_counter_trials(ctr, distinct, repeats, trials)
+    }
+
+    fn run_counter_trials<C>(mut ctr: C, distinct: u64, repeats: usize, trials: usize) -> Vec<f64>
+    where
+        C: DistinctCounter<u64>,
+        C::Error: std::fmt::Debug,
+    {
+        let mut stream_rng = StdRng::seed_from_u64(0x5eed);
+        let mut stream: Vec<u64> = (0..distinct)
+            .flat_map(|t| std::iter::repeat_n(t, repeats))
+            .collect();
+        (0..trials)
+            .map(|_| {
+                ctr.reset();
+                stream.shuffle(&mut stream_rng);
+                for t in &stream {
+                    ctr.add_token(*t);
+                }
+                ctr.estimate().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    pub fn estimate_is_exact_with_sufficient_space() {
+        for e in run_trials(64, 64, 3, 100) {
+            assert!(e == 64.0);
+        }
+    }
+
+    /// The tokens counted by the tests that check estimators are unbiased:
+    /// `DISTINCT_TOKENS` tokens that each appear twice, in random order.
+    const DISTINCT_TOKENS: u64 = 1000;
+
+    fn tokens() -> &'static [u64] {
+        static TOKENS: std::sync::OnceLock<Vec<u64>> = std::sync::OnceLock::new();
+        TOKENS.get_or_init(|| {
+            let mut tokens: Vec<u64> = (0..DISTINCT_TOKENS).chain(0..DISTINCT_TOKENS).collect();
+            tokens.shuffle(&mut StdRng::seed_from_u64(0x5eed));
+            tokens
+        })
+    }
+
+    /// Check that the mean of `estimate(seed)` over the seeds `0..trials` is
+    /// within 4 standard errors of `truth`, which an unbiased estimator fails
+    /// with probability < 1e-4. Returns the mean.
+    fn assert_unbiased(trials: usize, truth: f64, estimate: impl FnMut(u64) -> f64) -> f64 {
+        let estimates: Vec<f64> = (0..trials as u64).map(estimate).collect();
+        let n = trials as f64;
+        let mean = estimates.iter().sum::<f64>() / n;
+        let var = estimates.iter().map(|e| (e - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        let std_err = (var / n).sqrt();
+        println!("Mean {} standard error {}", mean, std_err);
+        assert!((mean - truth).abs() < 4.0 * std_err);
+        mean
+    }
+
+    #[test]
+    pub fn estimate_is_unbiased() {
+        let truth = DISTINCT_TOKENS as f64;
+        let mean = assert_unbiased(1000, truth, |seed| {
+            let mut ctr = CountUnique::new(StdRng::seed_from_u64(seed), 100);
+            for t in tokens() {
+                ctr.add_token(*t);
+            }
+            ctr.estimate().unwrap()
+        });
+        assert!((mean - truth).abs() < 0.02 * truth);
+    }
+
+    /// Return P(X <= k) for X ~ Binomial(n, x).
+    fn binomial_cdf(n: u64, x: f64, k: u64) -> f64 {
+        let mut pmf = (n as f64 * (1.0 - x).ln()).exp();
+        let mut cdf = pmf;
+        for i in 0..k.min(n) {
+            pmf *= (n - i) as f64 / (i + 1) as f64 * x / (1.0 - x);
+            cdf += pmf;
+        }
+        cdf.min(1.0)
+    }
+
+    #[test]
+    pub fn tail_respects_bound() {
+        const DISTINCT: u64 = 1000;
+        const SZ: usize = 100;
+        const TRIALS: usize = 4000;
+        const EPSILON: f64 = 0.15;
+        // With every token added once, the sketch keeps the SZ smallest
+        // priorities and p is the (SZ + 1)-th smallest, so the estimate SZ/p
+        // misses by more than a factor of (1 ± ε) exactly when at most SZ
+        // priorities are below SZ/(n(1 - ε)), or more than SZ are below
+        // SZ/(n(1 + ε)). This gives the exact δ for the buffer. Buffers from
+        // `threshold()` make misses too rare to observe, so use a small one.
+        let n = DISTINCT as f64;
+        let below = |x: f64| binomial_cdf(DISTINCT, x, SZ as u64);
+        let delta = 1.0 - below(SZ as f64 / (n * (1.0 + EPSILON)))
+            + below(SZ as f64 / (n * (1.0 - EPSILON)));
+        let estimates = run_trials(SZ, DISTINCT, 1, TRIALS);
+        let far = estimates
+            .iter()
+            .filter(|e| (*e - n).abs() > EPSILON * n)
+            .count();
+        let tail = far as f64 / TRIALS as f64;
+        // The number of misses is binomial, so the observed tail is within 4
+        // standard deviations of δ with probability > 0.9999.
+        let margin = 4.0 * (delta * (1.0 - delta) / TRIALS as f64).sqrt();
+        println!("Tail {} delta {} margin {}", tail, delta, margin);
+        assert!(delta > 0.05 && (tail - delta).abs() < margin);
+    }
+
+    #[test]
+    pub fn interval_covers_actual_count() {
+        const DISTINCT: u64 = 1000;
+        const TRIALS: usize = 500;
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 100);
+        let mut covered = 0;
+        for _ in 0..TRIALS {
+            ctr.reset();
+            for t in 0..DISTINCT {
+                ctr.add_token(t);
+            }
+            let (low, est, high) = ctr.estimate_with_interval(0.9).unwrap();
+            assert!(low <= est && est <= high);
+            if low <= DISTINCT as f64 && DISTINCT as f64 <= high {
+                covered += 1;
+            }
+        }
+        assert!(covered as f64 >= 0.9 * TRIALS as f64);
+        // Intervals are exact while nothing has been discarded.
+        ctr.reset();
+        ctr.add_token(1);
+        ctr.add_token(2);
+        assert!(ctr.estimate_with_interval(0.99).unwrap() == (2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    pub fn sample_is_uniform() {
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 16);
+        for t in 0..10u64 {
+            ctr.add_token(t);
+        }
+        assert!(ctr.sample().copied().eq(0..10u64));
+        assert!(ctr.sampling_probability() == 1.0);
+        // Count how often each of 100 distinct tokens is sampled.
+        let mut counts = [0usize; 100];
+        for _ in 0..1000 {
+            ctr.reset();
+            for _ in 0..3 {
+                for t in 0..100u64 {
+                    ctr.add_token(t);
+                }
+            }
+            assert!(ctr.sample().count() <= 16 && ctr.sampling_probability() < 1.0);
+            for t in ctr.sample() {
+                counts[*t as usize] += 1;
+            }
+        }
+        // Every token should be sampled about equally often.
+        let mean = counts.iter().sum::<usize>() as f64 / 100.0;
+        assert!(counts
+            .iter()
+            .all(|c| (*c as f64 - mean).abs() < 5.0 * mean.sqrt()));
+    }
+
+    #[test]
+    pub fn estimate_where_covers_subset_count() {
+        const DISTINCT: u64 = 10_000;
+        const TRIALS: usize = 200;
+        let mut rng = StdRng::seed_from_u64(0xc0ffee);
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(rng.gen()), 20_000);
+        for t in 0..DISTINCT {
+            ctr.add_token(t);
+        }
+        let (low, est, high) = ctr.estimate_where(|t| t % 4 == 0, 0.9).unwrap();
+        assert!(low == 2500.0 && est == 2500.0 && high == 2500.0);
+        let mut sum = 0.0;
+        let mut covered = 0;
+        for _ in 0..TRIALS {
+            let mut ctr = CountUnique::new(StdRng::seed_from_u64(rng.gen()), 1000);
+            for t in 0..DISTINCT {
+                ctr.add_token(t);
+            }
+            let (low, est, high) = ctr.estimate_where(|t| t % 4 == 0, 0.9).unwrap();
+            sum += est;
+            if low <= 2500.0 && 2500.0 <= high {
+                covered += 1;
+            }
+        }
+        let mean = sum / TRIALS as f64;
+        println!("Mean {} covered {}", mean, covered);
+        assert!((mean - 2500.0).abs() < 100.0);
+        assert!(covered >= 180);
+    }
+
+    #[test]
+    pub fn sum_and_mean_are_unbiased() {
+        const DISTINCT: u64 = 10_000;
+        const TRIALS: usize = 200;
+        // Sum and mean of t over 0..DISTINCT.
+        let sum = (DISTINCT * (DISTINCT - 1) / 2) as f64;
+        let mean = sum / DISTINCT as f64;
+        let mut rng = StdRng::seed_from_u64(0xc0ffee);
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(rng.gen()), 20_000);
+        for t in 0..DISTINCT {
+            ctr.add_token(t);
+        }
+        assert!(ctr.estimate_sum(|t| *t as f64) == (sum, 0.0));
+        assert!(ctr.estimate_mean(|t| *t as f64) == Some((mean, 0.0)));
+        let (mut sums, mut means) = (Vec::new(), Vec::new());
+        let (mut sum_var, mut mean_var) = (0.0, 0.0);
+        for _ in 0..TRIALS {
+            let mut ctr = CountUnique::new(StdRng::seed_from_u64(rng.gen()), 1000);
+            for t in 0..DISTINCT {
+                ctr.add_token(t);
+            }
+            let (s, v) = ctr.estimate_sum(|t| *t as f64);
+            sums.push(s);
+            sum_var += v / TRIALS as f64;
+            let (m, v) = ctr.estimate_mean(|t| *t as f64).unwrap();
+            means.push(m);
+            mean_var += v / TRIALS as f64;
+        }
+        let n = TRIALS as f64;
+        for (xs, actual, var) in [(sums, sum, sum_var), (means, mean, mean_var)] {
+            let m = xs.iter().sum::<f64>() / n;
+            let v = xs.iter().map(|x| (x - m).powi(2)).sum::<f64>() / (n - 1.0);
+            println!(
+                "Mean {} actual {} variance {} estimated {}",
+                m, actual, v, var
+            );
+            assert!((m - actual).abs() < 4.0 * (v / n).sqrt());
+            // The variance estimates should be in the right ballpark.
+            assert!(var > 0.5 * v && var < 2.0 * v);
+        }
+        ctr.reset();
+        assert!(ctr.estimate_mean(|t| *t as f64).is_none());
+    }
+
+    #[test]
+    pub fn quantiles_are_accurate() {
+        const DISTINCT: u64 = 10_000;
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 20_000);
+        assert!(ctr.quantile(0.5).is_none() && ctr.cdf(&0).is_none());
+        for t in 0..DISTINCT {
+            ctr.add_token(t);
+        }
+        assert!(ctr.quantile(0.0) == Some(&0) && ctr.quantile(1.0) == Some(&(DISTINCT - 1)));
+        assert!(ctr.quantile(0.5) == Some(&4999) && ctr.cdf(&4999) == Some(0.5));
+        assert!(ctr.quantile_error(0.9) == Some(0.0));
+        let mut rng = StdRng::seed_from_u64(0xc0ffee);
+        let mut within = 0;
+        for _ in 0..100 {
+            let mut ctr = CountUnique::new(StdRng::seed_from_u64(rng.gen()), 500);
+            // Repeats of small tokens must not shift the quantiles.
+            for t in 0..DISTINCT {
+                ctr.add_token(t);
+                ctr.add_token(t % 100);
+            }
+            let err = ctr.quantile_error(0.9).unwrap();
+            let cdf_ok = (0..DISTINCT)
+                .step_by(100)
+                .all(|t| (ctr.cdf(&t).unwrap() - (t + 1) as f64 / DISTINCT as f64).abs() <= err);
+            let median = *ctr.quantile(0.5).unwrap() as f64 / DISTINCT as f64;
+            if cdf_ok && (median - 0.5).abs() <= err {
+                within += 1;
+            }
+        }
+        assert!(within >= 90);
+    }
+
+    #[test]
+    pub fn cardinality_is_exact_until_full() {
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 10);
+        assert!(ctr.is_exact() && ctr.cardinality().unwrap() == Cardinality::Exact(0));
+        for t in 0..10u64 {
+            ctr.add_token(t);
+            ctr.add_token(t);
+        }
+        assert!(ctr.is_exact() && ctr.cardinality().unwrap() == Cardinality::Exact(10));
+        ctr.add_token(10);
+        assert!(!ctr.is_exact());
+        let c = ctr.cardinality().unwrap();
+        assert!(!c.is_exact() && c.value() == ctr.estimate().unwrap());
+        match c {
+            Cardinality::Approximate {
+                sampling_probability,
+                ..
+            } => assert!(sampling_probability == ctr.sampling_probability()),
+            Cardinality::Exact(_) => unreachable!(),
+        }
+        ctr.reset();
+        assert!(ctr.is_exact());
+    }
+
+    #[test]
+    pub fn batches_match_single_tokens() {
+        let stream: Vec<u64> = (0..10_000u64).map(|t| t * 7919 % 3000).collect();
+        let mut one = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 100);
+        for t in &stream {
+            one.add_token(*t);
+        }
+        let mut batch = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 100);
+        batch.add_tokens(stream[..5000].iter().copied());
+        batch.extend(stream[5000..].iter().copied());
+        assert!(batch.sampling_probability() == one.sampling_probability());
+        assert!(batch.sample().eq(one.sample()));
+        let mut hashed = CountUnique::with_hasher(Seeded(0), 100);
+        hashed.extend(stream.iter().copied());
+        let mut hashed_one = CountUnique::with_hasher(Seeded(0), 100);
+        for t in &stream {
+            hashed_one.add_token(*t);
+        }
+        assert!(hashed.sample().eq(hashed_one.sample()));
+    }
+
+    #[test]
+    pub fn borrowed_tokens_match_owned() {
+        let words: Vec<String> = (0..5000).map(|i| format!("word{}", i % 700)).collect();
+        let mut owned = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 50);
+        let mut borrowed = CountUnique::new(StdRng::seed_from_u64(0xc0ffee), 50);
+        let mut hashed = CountUnique::with_hasher(Seeded(0), 50);
+        let mut hashed_owned = CountUnique::with_hasher(Seeded(0), 50);
+        for w in &words {
+            owned.add_token(w.clone());
+            borrowed.add_token_ref(w.as_str());
+            hashed.add_token_ref(w.as_str());
+            hashed_owned.add_token(w.clone());
+        }
+        assert!(borrowed.sampling_probability() == owned.sampling_probability());
+        assert!(borrowed.sample().eq(owned.sample()));
+        assert!(hashed.sample().eq(hashed_owned.sample()));
+    }
+
+    #[test]
+    pub fn merge_is_exact_with_sufficient_space() {
+        let mut a = CountUnique::new(StdRng::seed_from_u64(1), 64);
+        let mut b = CountUnique::new(StdRng::seed_from_u64(2), 64);
+        for t in 0..40 {
+            a.add_token(t);
+            b.add_token(t + 20);
+        }
+        a.merge(&b);
+        assert!(a.estimate().unwrap() == 60.0);
+    }
+
+    #[test]
+    pub fn merge_is_unbiased() {
+        // Disjoint streams of different sizes.
+        assert_unbiased(1000, DISTINCT_TOKENS as f64, |seed| {
+            let mut a = CountUnique::new(StdRng::seed_from_u64(2 * seed), 100);
+            let mut b = CountUnique::new(StdRng::seed_from_u64(2 * seed + 1), 100);
+            for t in tokens() {
+                if *t % 3 == 0 {
+                    a.add_token(*t);
+                } else {
+                    b.add_token(*t);
+                }
+            }
+            a.merge(&b);
+            assert!(a.treap.size() <= 100);
+            a.estimate().unwrap()
+        });
+    }
+
+    #[test]
+    pub fn hashed_merge_is_unbiased() {
+        // Overlapping streams of different sizes, whose union is all tokens.
+        assert_unbiased(1000, DISTINCT_TOKENS as f64, |seed| {
+            let mut a = CountUnique::with_hasher(Seeded(seed), 100);
+            let mut b = CountUnique::with_hasher(Seeded(seed), 100);
+            for t in tokens() {
+                if *t < 400 {
+                    a.add_token(*t);
+                }
+                if *t >= 200 {
+                    b.add_token(*t);
+                }
+            }
+            a.merge(&b);
+            assert!(a.treap.size() <= 100);
+            a.estimate().unwrap()
+        });
+    }
+
+    /// A hasher seeded with a fixed key, so that tests are deterministic.
+    struct Seeded(u64);
+
+    impl BuildHasher for Seeded {
+        type Hasher = std::collections::hash_map::DefaultHasher;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            let mut h = std::collections::hash_map::DefaultHasher::new();
+            std::hash::Hasher::write_u64(&mut h, self.0);
+            h
+        }
+    }
+
+    #[test]
+    pub fn hashed_is_idempotent() {
+        let mut a = CountUnique::with_hasher(Seeded(7), 100);
+        let mut b = CountUnique::with_hasher(Seeded(7), 100);
+        for t in 0..1000u64 {
+            a.add_token(t);
+        }
+        let once = a.estimate().unwrap();
+        for t in (0..1000u64).rev() {
+            a.add_token(t);
+            b.add_token(t);
+        }
+        assert!(a.estimate().unwrap() == once);
+        assert!(b.estimate().unwrap() == once);
+        assert!(a
+            .treap
+            .iter()
+            .map(|e| e.value())
+            .eq(b.treap.iter().map(|e| e.value())));
+    }
+
+    #[test]
+    pub fn hashed_merge_matches_union() {
+        let mut a = CountUnique::with_hasher(Seeded(7), 100);
+        let mut b = CountUnique::with_hasher(Seeded(7), 100);
+        let mut union = CountUnique::with_hasher(Seeded(7), 100);
+        for t in 0..1000u64 {
+            a.add_token(t);
+            b.add_token(t + 500);
+            union.add_token(t);
+            union.add_token(t + 500);
+        }
+        a.merge(&b);
+        assert!(a.estimate().unwrap() == union.estimate().unwrap());
+    }
+
+    #[test]
+    pub fn overlap_estimates_are_unbiased() {
+        const TRIALS: u64 = 200;
+        // A is 0..6000 and B is 4000..10000: |A∪B| = 10000, |A∩B| = 2000.
+        let mut ctr_a = CountUnique::with_hasher(Seeded(0), 20_000);
+        let mut ctr_b = CountUnique::with_hasher(Seeded(0), 20_000);
+        assert!(ctr_a.jaccard(&ctr_b).is_none());
+        for t in 0..6000u64 {
+            ctr_a.add_token(t);
+            ctr_b.add_token(t + 4000);
+        }
+        assert!(ctr_a.union_estimate(&ctr_b).unwrap() == 10_000.0);
+        assert!(ctr_a.intersection_estimate(&ctr_b).unwrap() == 2000.0);
+        assert!(ctr_a.jaccard(&ctr_b) == Some(0.2));
+        let (mut union, mut both, mut jaccard) = (0.0, 0.0, 0.0);
+        for seed in 0..TRIALS {
+            let mut ctr_a = CountUnique::with_hasher(Seeded(seed), 500);
+            let mut ctr_b = CountUnique::with_hasher(Seeded(seed), 1000);
+            for t in 0..6000u64 {
+                ctr_a.add_token(t);
+                ctr_b.add_token(t + 4000);
+            }
+            union += ctr_a.union_estimate(&ctr_b).unwrap() / TRIALS as f64;
+            both += ctr_a.intersection_estimate(&ctr_b).unwrap() / TRIALS as f64;
+            jaccard += ctr_a.jaccard(&ctr_b).unwrap() / TRIALS as f64;
+        }
+        println!("Union {} intersection {} Jaccard {}", union, both, jaccard);
+        assert!((union - 10_000.0).abs() < 300.0);
+        assert!((both - 2000.0).abs() < 100.0);
+        assert!((jaccard - 0.2).abs() < 0.01);
+    }
+
+    #[test]
+    pub fn hashed_is_unbiased() {
+        assert_unbiased(1000, DISTINCT_TOKENS as f64, |seed| {
+            let mut ctr = CountUnique::with_hasher(Seeded(seed), 100);
+            for t in tokens() {
+                ctr.add_token(*t);
+            }
+            ctr.estimate().unwrap()
+        });
+    }
+
+    #[test]
+    pub fn priorities_are_precise() {
+        // Priorities drawn as f32 are multiples of 2^-24, which quantizes
+        // estimates once p is small.
+        let fine = |u: f64| (u * (1u64 << 24) as f64).fract() != 0.0;
+        let mut rng = StdRng::seed_from_u64(0);
+        assert!((0..100).all(|_| fine(PrioritySource::<u64>::priority(&mut rng, &0))));
+        let mut hashed = Hashed(Seeded(0));
+        assert!((0..100u64).filter(|t| fine(hashed.priority(t))).count() > 90);
+        // With 2 samples from 100k tokens p is small, but not quantized.
+        let mut ctr = CountUnique::new(StdRng::seed_from_u64(0), 2);
+        for t in 0..100_000u64 {
+            ctr.add_token(t);
+        }
+        assert!(ctr.p < 1e-3 && fine(ctr.p));
+    }
+
+    #[test]
+    pub fn fingerprints_are_unbiased() {
+        let ctr = FingerprintCountUnique::new(Seeded(1), StdRng::seed_from_u64(0xc0ffee), 65);
+        for e in run_counter_trials(ctr, 64, 3, 100) {
+            assert!(e == 64.0);
+        }
+        let ctr: FingerprintCountUnique<u64, _, _> =
+            FingerprintCountUnique::with_memory_budget(Seeded(1), StdRng::seed_from_u64(0), 4096);
+        // The budget holds as many elements as fit, and no more.
+        let node = ctr.memory_bound() / ctr.buffer_size();
+        assert!(ctr.memory_bound() <= 4096 && ctr.memory_bound() + node > 4096);
+        assert_unbiased(1000, DISTINCT_TOKENS as f64, |seed| {
+            let mut ctr = FingerprintCountUnique::new(Seeded(1), StdRng::seed_from_u64(seed), 100);
+            for t in tokens() {
+                ctr.add_token(t);
+            }
+            ctr.estimate().unwrap()
+        });
+    }
+
+    #[test]
+    pub fn boosting_tightens_tails() {
+        const DISTINCT: u64 = 1000;
+        const TRIALS: usize = 200;
+        assert!(groups_for(0.5) == 7 && groups_for(0.01) == 37);
+        let ctr: BoostedCountUnique<u64, StdRng> = BoostedCountUnique::new(0, 65, 3, 2);
+        assert!(ctr.groups() == 3 && ctr.group_size() == 2);
+        for e in run_counter_trials(ctr, 64, 3, 10) {
+            assert!(e == 64.0);
+        }
+        let far = |estimates: Vec<f64>| {
+            estimates
+                .iter()
+                .filter(|e| (*e / DISTINCT as f64 - 1.0).abs() > 0.1)
+                .count()
+        };
+        let single = far(run_trials(100, DISTINCT, 2, TRIALS));
+        let boosted: BoostedCountUnique<u64, StdRng> = BoostedCountUnique::new(0, 100, 9, 1);
+        let boosted = far(run_counter_trials(boosted, DISTINCT, 2, TRIALS));
+        println!("Far from actual: single {} boosted {}", single, boosted);
+        assert!(single > 40 && boosted * 5 < single);
+    }
+
+    #[test]
+    pub fn window_counts_recent_tokens() {
+        // The last 50 tokens are distinct, and fit in the sample.
+        let mut ctr = WindowedCountUnique::new(Seeded(0), 100, Window::Items(50));
+        for t in 0..10_000u64 {
+            ctr.add_token(t % 1000);
+        }
+        assert!(ctr.estimate().unwrap() == 50.0);
+        // The last 2000 of 20000 tokens cycling through 3000.
+        let mut sum = 0.0;
+        for seed in 0..100 {
+            let mut ctr = WindowedCountUnique::new(Seeded(seed), 50, Window::Items(2000));
+            for t in 0..20_000u64 {
+                ctr.add_token(t % 3000);
+            }
+            assert!(ctr.stored() < 1000);
+            sum += ctr.estimate().unwrap() / 100.0;
+        }
+        println!("Mean {}", sum);
+        assert!((sum - 2000.0).abs() < 60.0);
+    }
+
+    #[test]
+    pub fn window_expires_tokens() {
+        let window = Window::Duration(std::time::Duration::from_secs(1));
+        let start = std::time::Instant::now();
+        let at = |ms| start + std::time::Duration::from_millis(ms);
+        let mut sum = 0.0;
+        for seed in 0..100 {
+            let mut ctr = WindowedCountUnique::new(Seeded(seed), 50, window);
+            // One new token every millisecond for 3 seconds.
+            for t in 0..3000u64 {
+                ctr.add_token_at(t, at(t));
+            }
+            sum += ctr.estimate_at(at(2999)).unwrap() / 100.0;
+            assert!(ctr.estimate_at(at(3998)).unwrap() == 1.0);
+            assert!(ctr.estimate_at(at(5000)).unwrap() == 0.0);
+        }
+        println!("Mean {}", sum);
+        assert!((sum - 1000.0).abs() < 30.0);
+        // Tokens added after the end of the window are not counted.
+        let mut ctr = WindowedCountUnique::new(Seeded(0), 100, window);
+        for t in 0..50u64 {
+            ctr.add_token_at(t, at(t));
+        }
+        assert!(ctr.estimate_at(at(19)).unwrap() == 20.0);
+    }
+
+    #[test]
+    pub fn grouped_shares_budget() {
+        const TRIALS: u64 = 100;
+        let mut big = [0.0; 3];
+        for seed in 0..TRIALS {
+            let mut ctr = GroupedCountUnique::new(Seeded(seed), 2000);
+            // 200 small groups with 5 tokens, and groups with 2000, 4000 and 8000.
+            for t in 0..8000u64 {
+                ctr.add_token(t % 200 + 3, t / 200 % 5);
+                for (k, n) in [(0, 2000), (1, 4000), (2, 8000)] {
+                    ctr.add_token(k, t % n);
+                }
+            }
+            assert!(ctr.groups() == 203 && ctr.samples() <= 2000);
+            for k in 3..203 {
+                assert!(ctr.is_exact(&k) && ctr.estimate(&k) == Some(5.0));
+            }
+            assert!(!ctr.is_exact(&2) && ctr.estimate(&203).is_none());
+            let top: Vec<u64> = ctr.top(3).iter().map(|(k, _)| **k).collect();
+            assert!(top.len() == 3 && top.iter().all(|k| *k < 3));
+            for (k, e) in big.iter_mut().enumerate() {
+                *e += ctr.estimate(&(k as u64)).unwrap() / TRIALS as f64;
+            }
+        }
+        println!("Means {:?}", big);
+        for (e, n) in big.iter().zip([2000.0, 4000.0, 8000.0]) {
+            assert!((e / n - 1.0).abs() < 0.1);
+        }
+    }
+
+    #[test]
+    pub fn grouped_budget_bounds_groups() {
+        let node = Treap::<u64, f64>::node_size();
+        let mut ctr = GroupedCountUnique::with_memory_budget(Seeded(0), 1000 * node);
+        // Groups take up part of the budget, leaving less for samples.
+        for t in 0..2000u64 {
+            for k in 0..20u64 {
+                ctr.add_token(k, t);
+            }
+        }
+        assert!(ctr.samples() < 1000 && ctr.samples() > 900);
+        // A flood of new groups shrinks the large groups to one token each,
+        // and then the groups with the smallest estimates are dropped.
+        for k in 20..100_000u64 {
+            ctr.add_token(k, k);
+        }
+        assert!(ctr.groups() <= ctr.samples() && ctr.samples() < 1000);
+        assert!(ctr.estimate(&20).is_none() && !ctr.is_exact(&20));
+        for k in 0..20u64 {
+            assert!(ctr.estimate(&k).unwrap() > 100.0);
+        }
+        let top: Vec<u64> = ctr.top(20).iter().map(|(k, _)| **k).collect();
+        assert!(top.len() == 20 && top.iter().all(|k| *k < 20));
+        // Without a memory budget, every group still holds a sampled token.
+        let mut ctr = GroupedCountUnique::new(Seeded(0), 100);
+        for k in 0..1000u64 {
+            ctr.add_token(k, k);
+        }
+        assert!(ctr.groups() == 100 && ctr.samples() == 100);
+    }
+
+    #[test]
+    pub fn grouped_recreated_groups_are_not_exact() {
+        let mut ctr = GroupedCountUnique::new(Seeded(0), 2);
+        for k in 0..3u64 {
+            ctr.add_token(k, 0);
+        }
+        assert!(ctr.estimate(&0).is_none() && ctr.groups() == 2);
+        for t in 1..10u64 {
+            ctr.add_token(0, t);
+            assert!(!ctr.is_exact(&0));
+        }
+        ctr.reset();
+        ctr.add_token(0, 0);
+        assert!(ctr.is_exact(&0));
+    }
+
+    #[test]
+    pub fn halving_is_exact_with_sufficient_space() {
+        let ctr = HalvingCountUnique::new(StdRng::seed_from_u64(0xc0ffee), 65);
+        for e in run_counter_trials(ctr, 64, 3, 100) {
+            assert!(e == 64.0);
+        }
+    }
+
+    #[test]
+    pub fn halving_is_unbiased() {
+        assert_unbiased(1000, DISTINCT_TOKENS as f64, |seed| {
+            let mut ctr = HalvingCountUnique::new(StdRng::seed_from_u64(seed), 100);
+            for t in tokens() {
+                ctr.add_token(*t);
+            }
+            ctr.estimate().unwrap()
+        });
+    }
+
+    #[test]
+    pub fn halving_fails() {
+        // With a threshold of 1 the first token fills the buffer, and halving
+        // fails to remove it with probability 1/2.
+        let mut rng = StdRng::seed_from_u64(1);
+        let failed = (0..100)
+            .filter(|_| {
+                let mut ctr = HalvingCountUnique::new(StdRng::seed_from_u64(rng.gen()), 1);
+                ctr.add_token(0);
+                ctr.add_token(1);
+                matches!(ctr.estimate(), Err(HalvingError::Failed))
+            })
+            .count();
+        assert!(failed > 0 && failed < 100);
+        let e: Box<dyn std::error::Error> = HalvingError::Failed.into();
+        assert!(e.to_string().contains("did not remove") && e.source().is_none());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn checkpoint_round_trips() {
+        let mut ctr = CountUnique::with_hasher(Seeded(3), 16);
+        for t in 0..1000u64 {
+            ctr.add_token(t);
+        }
+        let c = ctr.checkpoint();
+        let bytes = c.to_bytes().unwrap();
+        assert!(Checkpoint::<u64>::from_bytes(&bytes).unwrap() == c);
+        let json = serde_json::to_string(&c).unwrap();
+        assert!(serde_json::from_str::<Checkpoint<u64>>(&json).unwrap() == c);
+        let mut restored = CountUnique::restore(Hashed(Seeded(3)), c).unwrap();
+        assert!(restored.estimate().unwrap() == ctr.estimate().unwrap());
+        for t in 1000..2000u64 {
+            ctr.add_token(t);
+            restored.add_token(t);
+        }
+        assert!(restored.checkpoint() == ctr.checkpoint());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn serialized_sketch_continues() {
+        use rand_chacha::ChaCha12Rng;
+        let mut ctr = CountUnique::new(ChaCha12Rng::seed_from_u64(7), 16);
+        for t in 0..1000u64 {
+            ctr.add_token(t % 300);
+        }
+        let json = serde_json::to_string(&ctr).unwrap();
+        let mut restored: CountUnique<u64, ChaCha12Rng> = serde_json::from_str(&json).unwrap();
+        for t in 0..1000u64 {
+            ctr.add_token(t);
+            restored.add_token(t);
+        }
+        assert!(restored.checkpoint() == ctr.checkpoint());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    pub fn bad_checkpoints_are_rejected() {
+        let mut ctr = CountUnique::with_hasher(Seeded(3), 4);
+        for t in 0..100u64 {
+            ctr.add_token(t);
+        }
+        let bytes = ctr.checkpoint().to_bytes().unwrap();
+        assert!(matches!(
+            Checkpoint::<u64>::from_bytes(&bytes[1..]),
+            Err(CheckpointError::BadMagic)
+        ));
+        let mut newer = bytes.clone();
+        newer[4] = 2;
+        assert!(matches!(
+            Checkpoint::<u64>::from_bytes(&newer),
+            Err(CheckpointError::UnsupportedVersion(2))
+        ));
+        assert!(matches!(
+            Checkpoint::<u64>::from_bytes(&bytes[..bytes.len() - 1]),
+            Err(CheckpointError::Encoding(_))
+        ));
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert!(matches!(
+            Checkpoint::<u64>::from_bytes(&longer),
+            Err(CheckpointError::Encoding(_))
+        ));
+        // A sample whose priority is above p cannot be in the buffer.
+        let mut json: serde_json::Value = serde_json::to_value(ctr.checkpoint()).unwrap();
+        json["samples"][0][1] = serde_json::json!(1.0);
+        let c: Checkpoint<u64> = serde_json::from_value(json).unwrap();
+        assert!(matches!(
+            CountUnique::restore(Hashed(Seeded(3)), c),
+            Err(CheckpointError::Invalid(_))
+        ));
+        // So can samples that are out of order.
+        let mut json: serde_json::Value = serde_json::to_value(ctr.checkpoint()).unwrap();
+        json["samples"].as_array_mut().unwrap().swap(0, 1);
+        let c: Checkpoint<u64> = serde_json::from_value(json).unwrap();
+        assert!(matches!(
+            CountUnique::restore(Hashed(Seeded(3)), c),
+            Err(CheckpointError::Invalid("samples are not sorted"))
+        ));
+    }
+
+    #[test]
+    pub fn threshold_matches_paper() {
+        // 12/0.25 * log2(8 * 1024 / 0.125) = 48 * 16.
+        assert!(threshold(0.5, 0.125, 1024) == 768);
+        let c = CountUnique::<u64, _>::with_accuracy(StdRng::seed_from_u64(0), 0.5, 0.125, 1024);
+        assert!(c.buffer_size() == 768);
+        assert!(c.guarantee() == Some((0.5, 0.125)));
+        assert!(CountUnique::<u64, _>::new(StdRng::seed_from_u64(0), 8)
+            .guarantee()
+            .is_none());
+    }
 }
@@ -0,0 +1,51 @@
+use std::hash::{BuildHasher, Hash};
+
+use rand::Rng;
+
+/// A source of priorities for the tokens added to a `CountUnique`.
+///
+/// Every random number generator is a priority source that draws a fresh
+/// priority for each occurrence of a token. `Hashed` instead derives the
+/// priority from a hash of the token.
+pub trait PrioritySource<T: ?Sized> {
+    /// Whether every occurrence of a token gets the same priority.
+    const DETERMINISTIC: bool;
+
+    /// Return a priority for `t`, uniformly distributed in `[0, 1)`.
+    /// Priorities should have at least 53 bits of precision, since the
+    /// sampling probability can become very small for large streams.
+    fn priority(&mut self, t: &T) -> f64;
+}
+
+impl<T: ?Sized, R: Rng> PrioritySource<T> for R {
+    const DETERMINISTIC: bool = false;
+
+    fn priority(&mut self, _t: &T) -> f64 {
+        self.gen::<f64>()
+    }
+}
+
+/// A priority source that uses a hash of the token as its priority.
+///
+/// With hashed priorities `CountUnique` is a bottom-k (KMV) sketch: it keeps
+/// the tokens with the smallest hashes. Adding a token that was already added
+/// does not change the sketch, and two sketches built with the same hasher
+/// sample the same tokens, so they can be compared or merged token by token.
+///
+/// Estimates are only unbiased if the hash is unpredictable from the tokens,
+/// so use a seeded hasher. To compare sketches, build them with clones of the
+/// same `BuildHasher`, e.g., the same `std::collections::hash_map::RandomState`.
+pub struct Hashed<S: BuildHasher>(pub S);
+
+impl<T, S> PrioritySource<T> for Hashed<S>
+where
+    T: Hash + ?Sized,
+    S: BuildHasher,
+{
+    const DETERMINISTIC: bool = true;
+
+    fn priority(&mut self, t: &T) -> f64 {
+        // Use the top 53 bits, which an f64 represents exactly.
+        (self.0.hash_one(t) >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
@@ -0,0 +1,259 @@
+use std::collections::{BTreeMap, BinaryHeap};
+use std::hash::{BuildHasher, Hash};
+use std::time::{Duration, Instant};
+
+use conv::*;
+
+use crate::{DistinctCounter, Hashed, PrioritySource};
+
+/// The window of a `WindowedCountUnique`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Window {
+    /// The last `n` tokens added.
+    Items(u64),
+    /// The tokens added within the last `Duration`.
+    Duration(Duration),
+}
+
+struct Entry<T> {
+    token: T,
+    priority: f64,
+    // Index of the token for `Window::Items`, nanoseconds since `start`
+    // for `Window::Duration`.
+    at: u64,
+}
+
+/// A CVM sketch that estimates the number of distinct tokens in a sliding
+/// window: the last `n` tokens, or the tokens added in the last `T` seconds.
+///
+/// Priorities are hashes of tokens as for `Hashed`, and each stored token
+/// keeps the time it was last seen. The estimate for a window uses the `sz`
+/// smallest priorities among tokens seen in the window, so tokens are kept
+/// as long as they could be among them: a token is evicted when it expires,
+/// or once `sz + 1` tokens seen more recently have smaller priorities. The
+/// number of stored tokens is therefore not fixed, for `w` distinct tokens
+/// in the window it is about `sz·(1 + ln(w/sz))`, and at most twice that
+/// between evictions.
+///
+/// # Example
+/// ```
+/// use count_unique_cvm::*;
+/// use std::collections::hash_map::RandomState;
+/// use std::time::Duration;
+///
+/// // Count distinct clients in the last 5 minutes.
+/// let window = Window::Duration(Duration::from_secs(300));
+/// let mut c = WindowedCountUnique::new(RandomState::new(), 100, window);
+/// for i in 0..1000 {
+///     c.add_token(i % 300);
+/// }
+/// println!("Estimated number of clients is {}", c.estimate().unwrap());
+/// ```
+pub struct WindowedCountUnique<T, S>
+where
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    // Stored tokens by the sequence number of their last occurrence.
+    entries: BTreeMap<u64, Entry<T>>,
+    // Sequence number of the last occurrence of each stored token.
+    seqs: BTreeMap<T, u64>,
+    priorities: Hashed<S>,
+    max_size: usize,
+    window: Window,
+    // Time of the first token, from which durations are measured.
+    start: Option<Instant>,
+    items: u64,
+    seq: u64,
+    // Evict dominated tokens once there are more entries than this.
+    limit: usize,
+}
+
+impl<T, S> WindowedCountUnique<T, S>
+where
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    /// Create a new `WindowedCountUnique` that estimates the number of
+    /// distinct tokens in `window` from the `sz` tokens with the smallest
+    /// hashes computed by `hasher`.
+    ///
+    /// # Panics
+    /// Function will panic if called with `sz < 1` or an empty window.
+    pub fn new(hasher: S, sz: usize, window: Window) -> Self {
+        if sz < 1 {
+            panic!("Cannot count without state");
+        }
+        if matches!(window, Window::Items(0)) || window == Window::Duration(Duration::ZERO) {
+            panic!("Cannot count in an empty window");
+        }
+        WindowedCountUnique {
+            entries: BTreeMap::new(),
+            seqs: BTreeMap::new(),
+            priorities: Hashed(hasher),
+            max_size: sz,
+            window,
+            start: None,
+            items: 0,
+            seq: 0,
+            limit: 2 * (sz + 1),
+        }
+    }
+
+    /// Return the window.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// Return the number of tokens currently stored.
+    pub fn stored(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Add a token seen now.
+    pub fn add_token(&mut self, t: T) {
+        self.add_token_at(t, Instant::now())
+    }
+
+    /// Add a token seen at `now`. Tokens must be added in order of time, and
+    /// `now` is ignored for `Window::Items`. Times may precede the creation
+    /// of the sketch, e.g., when replaying recorded events.
+    pub fn add_token_at(&mut self, t: T, now: Instant) {
+        let at = match self.window {
+            Window::Items(_) => self.items,
+            Window::Duration(_) => {
+                self.start.get_or_insert(now);
+                self.ticks(now)
+            }
+        };
+        self.items += 1;
+        let priority = self.priorities.priority(&t);
+        if let Some(seq) = self.seqs.insert(t.clone(), self.seq) {
+            self.entries.remove(&seq);
+        }
+        self.entries.insert(
+            self.seq,
+            Entry {
+                token: t,
+                priority,
+                at,
+            },
+        );
+        self.seq += 1;
+        if self.entries.len() > self.limit {
+            self.evict(at);
+            self.limit = 2 * self.entries.len().max(self.max_size + 1);
+        }
+    }
+
+    /// Return the estimated number of distinct tokens in the window ending
+    /// now.
+    pub fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
+        self.estimate_at(Instant::now())
+    }
+
+    /// Return the estimated number of distinct tokens in the window ending
+    /// at `now`, which is ignored for `Window::Items`. The estimate is exact
+    /// while the window holds at most `sz` distinct tokens.
+    ///
+    /// Tokens added after `now` are outside the window. Only the last
+    /// occurrence of each token is kept, so a token that was also added
+    /// before `now` is then missed as well: `now` should not precede the
+    /// latest token.
+    pub fn estimate_at(&self, now: Instant) -> Result<f64, PosOverflow<usize>> {
+        let now = match self.window {
+            Window::Items(_) => self.items.saturating_sub(1),
+            Window::Duration(_) => self.ticks(now),
+        };
+        // Keep the sz + 1 smallest priorities in the window. Priorities are
+        // non-negative, so their bits are ordered like the priorities.
+        let mut smallest = BinaryHeap::with_capacity(self.max_size + 2);
+        // Entries are in order of time, so those added after `now` come first.
+        for e in self.entries.values().rev().skip_while(|e| e.at > now) {
+            if !self.in_window(e, now) {
+                break;
+            }
+            smallest.push(e.priority.to_bits());
+            if smallest.len() > self.max_size + 1 {
+                smallest.pop();
+            }
+        }
+        if smallest.len() <= self.max_size {
+            return f64::value_from(smallest.len());
+        }
+        // The sz smallest priorities are below the (sz + 1)th smallest.
+        let p = f64::from_bits(*smallest.peek().unwrap());
+        Ok(f64::value_from(self.max_size)? / p)
+    }
+
+    /// Reset the state allowing the structure to be reused.
+    pub fn reset(&mut self) {
+        self.entries.clear();
+        self.seqs.clear();
+        self.items = 0;
+        self.start = None;
+        self.limit = 2 * (self.max_size + 1);
+    }
+
+    fn ticks(&self, now: Instant) -> u64 {
+        self.start
+            .map_or(0, |s| now.saturating_duration_since(s).as_nanos() as u64)
+    }
+
+    fn in_window(&self, e: &Entry<T>, now: u64) -> bool {
+        let age = match now.checked_sub(e.at) {
+            Some(age) => age,
+            // Added after `now`.
+            None => return false,
+        };
+        match self.window {
+            Window::Items(n) => age < n,
+            Window::Duration(d) => u128::from(age) < d.as_nanos(),
+        }
+    }
+
+    /// Remove tokens that are outside the window ending at `now`, or that
+    /// have `sz + 1` more recent tokens with smaller priorities and so can
+    /// never be among the `sz + 1` smallest in any window.
+    fn evict(&mut self, now: u64) {
+        let mut smallest = BinaryHeap::with_capacity(self.max_size + 2);
+        let mut evicted = Vec::new();
+        for (seq, e) in self.entries.iter().rev() {
+            let bits = e.priority.to_bits();
+            if !self.in_window(e, now)
+                || (smallest.len() > self.max_size && bits >= *smallest.peek().unwrap())
+            {
+                evicted.push(*seq);
+                continue;
+            }
+            smallest.push(bits);
+            if smallest.len() > self.max_size + 1 {
+                smallest.pop();
+            }
+        }
+        for seq in evicted {
+            let e = self.entries.remove(&seq).unwrap();
+            self.seqs.remove(&e.token);
+        }
+    }
+}
+
+impl<T, S> DistinctCounter<T> for WindowedCountUnique<T, S>
+where
+    T: Ord + Clone + Hash,
+    S: BuildHasher,
+{
+    type Error = PosOverflow<usize>;
+
+    fn add_token(&mut self, t: T) {
+        WindowedCountUnique::add_token(self, t)
+    }
+
+    fn estimate(&self) -> Result<f64, PosOverflow<usize>> {
+        WindowedCountUnique::estimate(self)
+    }
+
+    fn reset(&mut self) {
+        WindowedCountUnique::reset(self)
+    }
+}
@@ -7,6 +7,7 @@ use rand::prelude::*;
 use rand::rngs::StdRng;
 use statrs::statistics::Data;
 use statrs::statistics::Distribution;
+use statrs::statistics::Max;
 use statrs::statistics::Median;
 
 use std::collections::HashSet;
@@ -32,6 +33,8 @@ struct Estimator<R: Rng> {
     cvm: CountUnique<u64, R>,
     pub estimates: Vec<f64>,
     pub actual_values: Vec<usize>,
+    pub heights: Vec<f64>,
+    pub average_depths: Vec<f64>,
     min_estimate: f64,
     max_estimate: f64,
     min_token: u64,
@@ -46,6 +49,8 @@ impl<R: Rng> Estimator<R> {
             cvm: CountUnique::new(treap_rng, sz),
             estimates: vec![],
             actual_values: vec![],
+            heights: vec![],
+            average_depths: vec![],
             min_estimate: f64::MAX,
             max_estimate: f64::MIN,
             stream_rng,
@@ -71,6 +76,10 @@ impl<R: Rng> Estimator<R> {
         };
         self.estimates.push(estimate);
         self.actual_values.push(self.token_set.len());
+        self.heights.push(self.cvm.treap_height() as f64);
+        if let Some(d) = self.cvm.treap_average_depth() {
+            self.average_depths.push(d);
+        }
         (estimate, self.token_set.len())
     }
 }
@@ -129,6 +138,23 @@ fn main() {
         estimated.variance().unwrap(),
         real.variance().unwrap()
     );
+    let stats = estimator.cvm.treap_stats();
+    let heights = Data::new(estimator.heights.clone());
+    let depths = Data::new(estimator.average_depths.clone());
+    println!(
+        "Treap: height mean {} max {}, average depth mean {}",
+        heights.mean().unwrap(),
+        heights.max(),
+        depths.mean().unwrap_or(0.0)
+    );
+    println!(
+        "Treap ops: {} rotations/op {} comparisons/op {} visits/op {} max depth {}",
+        stats.operations,
+        stats.rotations_per_op(),
+        stats.comparisons_per_op(),
+        stats.nodes_visited_per_op(),
+        stats.total.max_depth
+    );
     if let Some(fname) = args.tokens_cdf {
         let mut sorted_estimates = estimator.estimates.clone();
         draw_cdf(
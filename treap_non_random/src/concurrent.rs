@@ -0,0 +1,136 @@
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{Element, Treap};
+
+/// A `Treap` that can be shared between threads.
+///
+/// Readers (`get`, `get_max`, `with_max`, `size`) take a shared lock and can
+/// run concurrently with each other; writers (`insert`, `delete`, `reset`)
+/// take an exclusive lock. Lookups return clones of elements so that no lock
+/// is held once they return, use `with_max` or `read` to avoid cloning.
+///
+/// # Panics
+/// All methods panic if a thread panicked while holding the write lock,
+/// since the treap may have been left in an inconsistent state.
+///
+/// # Example
+/// ```
+/// use treap_non_random::{ConcurrentTreap, Element};
+///
+/// let t = ConcurrentTreap::new();
+/// std::thread::scope(|s| {
+///     s.spawn(|| {
+///         for i in 0..100 {
+///             t.insert(Element::new(i, i));
+///         }
+///     });
+///     s.spawn(|| {
+///         let m = t.get_max().map(|e| *e.priority());
+///         assert!(m.unwrap_or(0) < 100);
+///     });
+/// });
+/// assert_eq!(*t.get_max().unwrap().priority(), 99);
+/// ```
+pub struct ConcurrentTreap<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    treap: RwLock<Treap<T, P>>,
+}
+
+impl<T, P> Default for ConcurrentTreap<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, P> From<Treap<T, P>> for ConcurrentTreap<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    fn from(treap: Treap<T, P>) -> Self {
+        ConcurrentTreap {
+            treap: RwLock::new(treap),
+        }
+    }
+}
+
+impl<T, P> ConcurrentTreap<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    /// Create a new, empty ConcurrentTreap.
+    pub fn new() -> Self {
+        Treap::new().into()
+    }
+
+    /// Insert (or update) an item.
+    pub fn insert(&self, element: Element<T, P>) {
+        self.write().insert(element)
+    }
+
+    /// Delete element whose value is `e`. Returns `true` if an element
+    /// was removed.
+    pub fn delete(&self, e: &T) -> bool {
+        self.write().delete(e)
+    }
+
+    /// Reset the treap, removing all items.
+    pub fn reset(&self) {
+        self.write().reset()
+    }
+
+    /// Get the number of elements.
+    pub fn size(&self) -> usize {
+        self.read().size()
+    }
+
+    /// Call `f` with the element with the highest priority, without cloning it.
+    /// Writers are blocked until `f` returns.
+    pub fn with_max<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(Option<&Element<T, P>>) -> R,
+    {
+        f(self.read().get_max())
+    }
+
+    /// Lock the treap for reading. Writers are blocked until the guard is dropped.
+    pub fn read(&self) -> RwLockReadGuard<'_, Treap<T, P>> {
+        self.treap.read().unwrap()
+    }
+
+    /// Lock the treap for writing. Other readers and writers are blocked until
+    /// the guard is dropped.
+    pub fn write(&self) -> RwLockWriteGuard<'_, Treap<T, P>> {
+        self.treap.write().unwrap()
+    }
+
+    /// Consume `self`, returning the underlying treap.
+    pub fn into_inner(self) -> Treap<T, P> {
+        self.treap.into_inner().unwrap()
+    }
+}
+
+impl<T, P> ConcurrentTreap<T, P>
+where
+    T: Ord + Clone,
+    P: PartialOrd + Clone,
+{
+    /// Get a copy of the element with the highest priority, otherwise return `None`.
+    pub fn get_max(&self) -> Option<Element<T, P>> {
+        self.with_max(|m| m.cloned())
+    }
+
+    /// Get a copy of the element whose value is `e` if it exists, otherwise
+    /// return `None`.
+    pub fn get(&self, e: &T) -> Option<Element<T, P>> {
+        self.read().get_ref(e).cloned()
+    }
+}
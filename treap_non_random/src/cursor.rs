@@ -0,0 +1,299 @@
+use std::{cmp::Ordering, ptr};
+
+use crate::data::Element;
+use crate::treap_node::{TreapChild, TreapNode};
+use crate::Treap;
+
+type Link<T, P> = Option<Box<TreapNode<T, P>>>;
+
+/// A cursor over the elements of a `Treap` in value order, created by
+/// `Treap::cursor_front()` or `Treap::cursor_at()`.
+///
+/// A cursor either points at an element or at a "ghost" position that lies
+/// after the last and before the first element. Moving past either end lands
+/// on the ghost position, and moving again wraps around. Moving to the next or
+/// previous element takes amortized constant time.
+pub struct Cursor<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    root: Option<&'a TreapNode<T, P>>,
+    // Nodes from the root to the current element, empty at the ghost position.
+    path: Vec<&'a TreapNode<T, P>>,
+}
+
+impl<'a, T, P> Cursor<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    pub(crate) fn front(root: Option<&'a TreapNode<T, P>>) -> Self {
+        let mut c = Cursor {
+            root,
+            path: Vec::new(),
+        };
+        c.push_extreme(root, TreapChild::Left);
+        c
+    }
+
+    pub(crate) fn at(root: Option<&'a TreapNode<T, P>>, key: &T) -> Self {
+        let mut c = Cursor {
+            root,
+            path: Vec::new(),
+        };
+        // Length of the path to the smallest element greater than `key` seen so far.
+        let mut successor = 0;
+        let mut node = root;
+        while let Some(n) = node {
+            c.path.push(n);
+            match n.element.value().cmp(key) {
+                Ordering::Equal => return c,
+                Ordering::Greater => {
+                    successor = c.path.len();
+                    node = n.left.as_deref();
+                }
+                Ordering::Less => node = n.right.as_deref(),
+            }
+        }
+        c.path.truncate(successor);
+        c
+    }
+
+    fn push_extreme(&mut self, mut node: Option<&'a TreapNode<T, P>>, side: TreapChild) {
+        while let Some(n) = node {
+            self.path.push(n);
+            node = match side {
+                TreapChild::Left => n.left.as_deref(),
+                TreapChild::Right => n.right.as_deref(),
+            };
+        }
+    }
+
+    /// Pop nodes off the path until the one just popped was the `side` child
+    /// of the new current node, or the path is empty.
+    fn climb(&mut self, side: TreapChild) {
+        while let Some(child) = self.path.pop() {
+            if let Some(parent) = self.path.last() {
+                let c = match side {
+                    TreapChild::Left => parent.left.as_deref(),
+                    TreapChild::Right => parent.right.as_deref(),
+                };
+                if c.is_some_and(|c| ptr::eq(c, child)) {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Get the element the cursor points at, or `None` at the ghost position.
+    pub fn element(&self) -> Option<&'a Element<T, P>> {
+        self.path.last().map(|n| &n.element)
+    }
+
+    /// Move to the next element in value order.
+    pub fn move_next(&mut self) {
+        match self.path.last() {
+            None => self.push_extreme(self.root, TreapChild::Left),
+            Some(&n) => match n.right.as_deref() {
+                Some(r) => self.push_extreme(Some(r), TreapChild::Left),
+                None => self.climb(TreapChild::Left),
+            },
+        }
+    }
+
+    /// Move to the previous element in value order.
+    pub fn move_prev(&mut self) {
+        match self.path.last() {
+            None => self.push_extreme(self.root, TreapChild::Right),
+            Some(&n) => match n.left.as_deref() {
+                Some(l) => self.push_extreme(Some(l), TreapChild::Right),
+                None => self.climb(TreapChild::Right),
+            },
+        }
+    }
+}
+
+/// A cursor over the elements of a `Treap` that can also remove elements,
+/// created by `Treap::cursor_front_mut()` or `Treap::cursor_at_mut()`.
+///
+/// Navigation works like `Cursor`. While the cursor is alive the path from
+/// the root to the current element is detached from the treap (a zipper), so
+/// that removing the current element does not need another lookup. The treap
+/// is put back together when the cursor is dropped.
+pub struct CursorMut<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    treap: &'a mut Treap<T, P>,
+    // Subtree rooted at the current element. At the ghost position this holds
+    // the entire treap and `path` is empty.
+    focus: Link<T, P>,
+    // Ancestors of `focus`, each missing its child on the given side.
+    path: Vec<(Box<TreapNode<T, P>>, TreapChild)>,
+    ghost: bool,
+}
+
+impl<'a, T, P> CursorMut<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    fn new(treap: &'a mut Treap<T, P>) -> Self {
+        let focus = treap.root.take();
+        CursorMut {
+            treap,
+            focus,
+            path: Vec::new(),
+            ghost: true,
+        }
+    }
+
+    pub(crate) fn front(treap: &'a mut Treap<T, P>) -> Self {
+        let mut c = Self::new(treap);
+        c.move_next();
+        c
+    }
+
+    pub(crate) fn at(treap: &'a mut Treap<T, P>, key: &T) -> Self {
+        let mut c = Self::new(treap);
+        c.ghost = false;
+        c.treap.stats.begin();
+        c.seek(key);
+        c
+    }
+
+    fn has_child(&self, side: TreapChild) -> bool {
+        self.focus.as_ref().is_some_and(|n| match side {
+            TreapChild::Left => n.left.is_some(),
+            TreapChild::Right => n.right.is_some(),
+        })
+    }
+
+    fn descend(&mut self, side: TreapChild) {
+        let mut node = self.focus.take().unwrap();
+        self.focus = match side {
+            TreapChild::Left => node.left.take(),
+            TreapChild::Right => node.right.take(),
+        };
+        self.path.push((node, side));
+    }
+
+    fn descend_all(&mut self, side: TreapChild) {
+        while self.has_child(side) {
+            self.descend(side);
+        }
+    }
+
+    fn ascend(&mut self) -> Option<TreapChild> {
+        let (mut parent, side) = self.path.pop()?;
+        match side {
+            TreapChild::Left => parent.left = self.focus.take(),
+            TreapChild::Right => parent.right = self.focus.take(),
+        }
+        self.focus = Some(parent);
+        Some(side)
+    }
+
+    /// Ascend until we leave a `side` subtree, or move to the ghost position
+    /// if there is no such ancestor.
+    fn climb(&mut self, side: TreapChild) {
+        loop {
+            match self.ascend() {
+                Some(s) if s == side => return,
+                Some(_) => {}
+                None => {
+                    self.ghost = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Move to `key`, or the smallest element greater than `key`, searching
+    /// from the current subtree and then its ancestors.
+    fn seek(&mut self, key: &T) {
+        while let Some(n) = self.focus.as_ref() {
+            self.treap.stats.visit();
+            self.treap.stats.comparison();
+            let side = match n.element.value().cmp(key) {
+                Ordering::Equal => return,
+                Ordering::Greater => TreapChild::Left,
+                Ordering::Less => TreapChild::Right,
+            };
+            if self.has_child(side) {
+                self.descend(side);
+            } else if side == TreapChild::Left {
+                return;
+            } else {
+                break;
+            }
+        }
+        self.climb(TreapChild::Left);
+    }
+
+    /// Get the element the cursor points at, or `None` at the ghost position.
+    pub fn element(&self) -> Option<&Element<T, P>> {
+        if self.ghost {
+            None
+        } else {
+            self.focus.as_ref().map(|n| &n.element)
+        }
+    }
+
+    /// Move to the next element in value order.
+    pub fn move_next(&mut self) {
+        if self.ghost {
+            if self.focus.is_some() {
+                self.ghost = false;
+                self.descend_all(TreapChild::Left);
+            }
+        } else if self.has_child(TreapChild::Right) {
+            self.descend(TreapChild::Right);
+            self.descend_all(TreapChild::Left);
+        } else {
+            self.climb(TreapChild::Left);
+        }
+    }
+
+    /// Move to the previous element in value order.
+    pub fn move_prev(&mut self) {
+        if self.ghost {
+            if self.focus.is_some() {
+                self.ghost = false;
+                self.descend_all(TreapChild::Right);
+            }
+        } else if self.has_child(TreapChild::Left) {
+            self.descend(TreapChild::Left);
+            self.descend_all(TreapChild::Right);
+        } else {
+            self.climb(TreapChild::Right);
+        }
+    }
+
+    /// Remove the current element and return it. The cursor moves to the
+    /// next element. Returns `None`, and does nothing, at the ghost position.
+    pub fn remove_current(&mut self) -> Option<Element<T, P>> {
+        if self.ghost {
+            return None;
+        }
+        self.treap.stats.begin();
+        self.treap.stats.visit();
+        let e = TreapNode::take_root(&mut self.focus, &self.treap.stats)?;
+        self.treap.size -= 1;
+        self.seek(e.value());
+        Some(e)
+    }
+}
+
+impl<T, P> Drop for CursorMut<'_, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    fn drop(&mut self) {
+        while self.ascend().is_some() {}
+        self.treap.root = self.focus.take();
+    }
+}
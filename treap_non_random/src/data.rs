@@ -16,6 +16,8 @@ use std::fmt::{Display, Formatter, Result};
 /// assert_eq!(*e0.value(), "Hello");
 /// assert_eq!(*e0.priority(), 22);
 /// ```
+#[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Element<T: Ord, P: PartialOrd> {
     value: T,
     priority: P,
@@ -0,0 +1,62 @@
+use crate::data::Element;
+use crate::treap_node::TreapNode;
+
+/// An iterator over the elements of a `Treap` in increasing value order,
+/// created by `Treap::iter()`.
+pub struct Iter<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    // Nodes whose element and right subtree have not been visited yet.
+    stack: Vec<&'a TreapNode<T, P>>,
+    remaining: usize,
+}
+
+impl<'a, T, P> Iter<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    pub(crate) fn new(root: Option<&'a TreapNode<T, P>>, size: usize) -> Self {
+        let mut i = Iter {
+            stack: Vec::new(),
+            remaining: size,
+        };
+        i.push_left(root);
+        i
+    }
+
+    fn push_left(&mut self, mut node: Option<&'a TreapNode<T, P>>) {
+        while let Some(n) = node {
+            self.stack.push(n);
+            node = n.left.as_deref();
+        }
+    }
+}
+
+impl<'a, T, P> Iterator for Iter<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    type Item = &'a Element<T, P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let n = self.stack.pop()?;
+        self.push_left(n.right.as_deref());
+        self.remaining -= 1;
+        Some(&n.element)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<T, P> ExactSizeIterator for Iter<'_, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+}
@@ -9,7 +9,9 @@
 //!
 //! Traditionally, random priorities are used and thus in expectation the tree is balanced.
 //! However, Treaps are not a particularly interesting way to build sets or hashmaps, you are
-//! better served using the standard Rust BTree instead.
+//! better served using the standard Rust BTree instead. If you do want a treap based set,
+//! enable the `rand` feature: `RandomTreapSet` assigns random priorities and provides a
+//! balanced (in expectation) ordered set.
 //!
 //! This implementation exists instead to be used in cases where accessing elements with max
 //! priorities and checking existence are both necessary, as is the case with the CVM algorithm
@@ -25,21 +27,62 @@
 //! t.insert(Element::new("lo".into(), -22));
 //! t.insert(Element::new("hi".into(), 65536));
 //! let max = t.get_max();
-//! assert!(max.is_some() && max.unwrap().value().eq("hi".into()));
+//! assert!(max.is_some() && max.unwrap().value() == "hi");
 //! let lo = t.get("lo".into());
 //! assert!(lo.is_some());
 //! let no = t.get("missing".into());
 //! assert!(no.is_none());
 //! ```
+//!
+//! # Concurrency
+//! `ConcurrentTreap` wraps a `Treap` in a reader-writer lock, so that many
+//! threads can query the maximum while another inserts.
+//!
+//! # Serialization
+//! With the `serde` feature enabled, `Element` and `Treap` implement
+//! `Serialize` and `Deserialize`. A treap is stored as its elements in value
+//! order, and deserializing checks that the result is a valid treap.
+//! `Treap::from_sorted()` performs the same linear-time construction for
+//! elements that are already sorted by value. The `shape` module provides a
+//! compact encoding that also preserves the shape of the tree.
+//!
+//! # Statistics
+//! Since priorities are not random, how balanced the tree is depends on the
+//! workload. `Treap::height()` and `Treap::average_depth()` describe the current
+//! shape of the tree, and enabling the `stats` feature makes the treap count
+//! rotations, comparisons and visited nodes, which can be read using
+//! `Treap::stats()`.
 
 #![deny(missing_docs)]
+mod concurrent;
+mod cursor;
 mod data;
+mod iter;
+#[cfg(feature = "rand")]
+mod random_set;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "serde")]
+pub mod shape;
+mod stats;
 mod treap_node;
 use alloc_counter::no_alloc;
+pub use concurrent::ConcurrentTreap;
+pub use cursor::{Cursor, CursorMut};
 pub use data::Element;
+pub use iter::Iter;
+#[cfg(feature = "rand")]
+pub use random_set::RandomTreapSet;
+#[cfg(feature = "serde")]
+pub use serialize::InvalidTreap;
+use stats::Recorder;
+#[cfg(feature = "stats")]
+pub use stats::{OpStats, Stats};
 use treap_node::TreapNode;
 
 use std::{
+    borrow::Borrow,
+    cmp::Ordering,
     fmt::{Display, Formatter, Result},
     mem,
 };
@@ -52,6 +95,7 @@ where
 {
     root: Option<Box<TreapNode<T, P>>>,
     size: usize,
+    stats: Recorder,
 }
 
 impl<T, P> Default for Treap<T, P>
@@ -74,6 +118,7 @@ where
         Treap {
             root: None,
             size: 0,
+            stats: Recorder::new(),
         }
     }
 
@@ -89,13 +134,15 @@ where
 
     /// Insert (or update) an item.
     pub fn insert(&mut self, element: Element<T, P>) {
+        self.stats.begin();
         match &mut self.root {
             None => {
+                self.stats.visit();
                 self.set_root(element);
                 self.size = 1;
             }
             Some(e) => {
-                if e.insert_or_replace(element.into()) {
+                if e.insert_or_replace(element.into(), &self.stats) {
                     self.size += 1;
                 }
             }
@@ -111,51 +158,145 @@ where
     /// Get an element whose value is `e` if it exists, otherwise return `None`.
     #[no_alloc]
     pub fn get(&self, e: T) -> Option<&Element<T, P>> {
-        self.root.as_ref().and_then(|n| n.get(e))
+        self.get_ref(&e)
     }
 
-    /// Delete element whose value is `e`.
+    /// Get an element whose value is `e` without taking ownership of `e`.
     #[no_alloc]
-    pub fn delete(&mut self, e: &T) {
-        match &mut self.root {
-            None => {}
+    pub fn get_ref(&self, e: &T) -> Option<&Element<T, P>> {
+        self.get_by(e)
+    }
+
+    /// Get an element whose value is `e`, where, as for `BTreeMap`, `e` can
+    /// be any borrowed form of the value type.
+    #[no_alloc]
+    pub fn get_by<Q>(&self, e: &Q) -> Option<&Element<T, P>>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.stats.begin();
+        self.root.as_ref().and_then(|n| n.get(e, &self.stats))
+    }
+
+    /// Check whether an element whose value is `e` exists.
+    #[no_alloc]
+    pub fn contains(&self, e: &T) -> bool {
+        self.get_ref(e).is_some()
+    }
+
+    /// Check whether an element whose value is `e` exists, where `e` can be
+    /// any borrowed form of the value type.
+    #[no_alloc]
+    pub fn contains_by<Q>(&self, e: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.get_by(e).is_some()
+    }
+
+    /// Delete element whose value is `e`. Returns `true` if an element
+    /// was removed.
+    #[no_alloc]
+    pub fn delete(&mut self, e: &T) -> bool {
+        self.delete_by(e)
+    }
+
+    /// Delete element whose value is `e`, where `e` can be any borrowed form
+    /// of the value type. Returns `true` if an element was removed.
+    #[no_alloc]
+    pub fn delete_by<Q>(&mut self, e: &Q) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        self.stats.begin();
+        match self.root.as_deref_mut() {
+            None => false,
             Some(r) => {
-                let deleted = if r.element.value() == e {
-                    if r.left.is_none() && r.right.is_none() {
-                        self.reset();
-                        false
-                    } else if r.left.is_none() && r.right.is_some() {
-                        r.rotate_left();
-                        r.delete(e)
-                    } else if r.right.is_none() && r.left.is_some() {
-                        r.rotate_right();
-                        r.delete(e)
-                    } else {
-                        let p_left = r.left.as_ref().unwrap().element.priority();
-                        let p_right = r.right.as_ref().unwrap().element.priority();
-                        if p_left < p_right {
-                            r.rotate_left();
-                            r.delete(e)
-                        } else {
-                            r.rotate_right();
-                            r.delete(e)
-                        }
+                self.stats.visit();
+                self.stats.comparison();
+                let deleted = match r.element.value().borrow().cmp(e) {
+                    Ordering::Equal => {
+                        TreapNode::take_root(&mut self.root, &self.stats);
+                        true
                     }
-                } else {
-                    r.delete(e)
+                    ord => r.delete(ord, e, &self.stats),
                 };
                 if deleted {
                     self.size -= 1;
                 }
+                deleted
             }
         }
     }
 
+    /// Get the number of bytes allocated for each element, not counting
+    /// any heap memory owned by the element's value or priority.
+    pub fn node_size() -> usize {
+        mem::size_of::<TreapNode<T, P>>()
+    }
+
     /// Get the number of elements in `self`.
     pub fn size(&self) -> usize {
         self.size
     }
 
+    /// Iterate over elements in increasing order of value.
+    pub fn iter(&self) -> Iter<'_, T, P> {
+        Iter::new(self.root.as_deref(), self.size)
+    }
+
+    /// Get a cursor pointing at the element with the smallest value.
+    pub fn cursor_front(&self) -> Cursor<'_, T, P> {
+        Cursor::front(self.root.as_deref())
+    }
+
+    /// Get a cursor pointing at the element whose value is `key`. If there is
+    /// no such element the cursor points at the smallest element greater than
+    /// `key`, or at the ghost position if there is none.
+    pub fn cursor_at(&self, key: &T) -> Cursor<'_, T, P> {
+        Cursor::at(self.root.as_deref(), key)
+    }
+
+    /// Like `cursor_front()`, but the cursor can also remove elements.
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, T, P> {
+        CursorMut::front(self)
+    }
+
+    /// Like `cursor_at()`, but the cursor can also remove elements.
+    pub fn cursor_at_mut(&mut self, key: &T) -> CursorMut<'_, T, P> {
+        CursorMut::at(self, key)
+    }
+
+    /// Get the height of the treap, i.e., the number of nodes on the longest
+    /// path from the root. An empty treap has height 0.
+    pub fn height(&self) -> usize {
+        self.root.as_ref().map(|r| r.height()).unwrap_or(0)
+    }
+
+    /// Get the average depth of elements in the treap, where the root has
+    /// depth 1. Returns `None` if the treap is empty.
+    pub fn average_depth(&self) -> Option<f64> {
+        self.root
+            .as_ref()
+            .map(|r| r.depth_sum(1) as f64 / self.size as f64)
+    }
+
+    /// Get statistics about the operations performed on this treap. Statistics
+    /// are not cleared by `reset()`, use `reset_stats()` instead.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        self.stats.get()
+    }
+
+    /// Clear the statistics collected so far.
+    #[cfg(feature = "stats")]
+    pub fn reset_stats(&mut self) {
+        self.stats.reset();
+    }
+
     #[cfg(test)]
     fn maintains_heap(&self) -> bool {
         self.root
@@ -201,7 +342,7 @@ mod test {
         assert!(t.size() == 7);
         let m = t.get_max();
         assert!(m.is_some());
-        assert!(m.unwrap().value().eq("hi".into()));
+        assert!(m.unwrap().value() == "hi");
     }
 
     #[test]
@@ -211,15 +352,24 @@ mod test {
         let before = t.get("lo".into());
         assert!(before.is_some());
         assert!(t.size() == 7);
-        t.delete(&"lo".into());
+        assert!(t.delete(&"lo".into()));
         assert!(t.maintains_heap());
         let after = t.get("lo".into());
         assert!(after.is_none());
         assert!(t.size() == 6);
-        t.delete(&"lo".into());
+        assert!(!t.delete(&"lo".into()));
         assert!(t.size() == 6);
     }
 
+    #[test]
+    fn borrowed_lookups_work() {
+        let mut t = setup_standard_treap();
+        assert!(t.contains_by("lo") && t.get_by("lo").is_some());
+        assert!(t.delete_by("lo"));
+        assert!(t.maintains_heap() && t.size() == 6);
+        assert!(!t.contains_by("lo") && !t.delete_by("lo"));
+    }
+
     #[test]
     fn insert_works() {
         let mut t = setup_standard_treap();
@@ -235,4 +385,280 @@ mod test {
         assert!(t.size() == prev_size + 2);
         assert!(*(t.get_max().unwrap().priority()) == prev_max + 1);
     }
+
+    #[test]
+    fn update_keeps_subtrees() {
+        let mut t = setup_standard_treap();
+        // Lower the root's priority below every other element.
+        t.insert(Element::new("hi".into(), -100));
+        assert!(t.maintains_heap());
+        assert!(t.size() == 7 && t.iter().count() == 7);
+        assert!(*t.get("hi".into()).unwrap().priority() == -100);
+        // Raise it back above every other element.
+        t.insert(Element::new("hi".into(), 100));
+        assert!(t.maintains_heap() && t.iter().count() == 7);
+        assert!(t.get_max().unwrap().value() == "hi");
+    }
+
+    #[test]
+    fn iter_is_ordered() {
+        let mut t = setup_standard_treap();
+        let values: Vec<String> = t.iter().map(|e| e.value().clone()).collect();
+        assert!(values == vec!["A", "cc", "hi", "lo", "xx", "y", "z"]);
+        assert!(t.iter().len() == 7);
+        t.delete(&"hi".into());
+        assert!(t.iter().count() == 6);
+    }
+
+    #[test]
+    fn cursor_works() {
+        let t = setup_standard_treap();
+        let mut c = t.cursor_at(&"hi".into());
+        assert!(c.element().unwrap().value() == "hi");
+        c.move_prev();
+        assert!(c.element().unwrap().value() == "cc");
+        c.move_next();
+        c.move_next();
+        assert!(c.element().unwrap().value() == "lo");
+        // Missing keys land on the next element, or the ghost position.
+        let c = t.cursor_at(&"m".into());
+        assert!(c.element().unwrap().value() == "xx");
+        let mut c = t.cursor_at(&"zz".into());
+        assert!(c.element().is_none());
+        c.move_prev();
+        assert!(c.element().unwrap().value() == "z");
+        let mut c = t.cursor_front();
+        let mut forward = vec![];
+        while let Some(e) = c.element() {
+            forward.push(e.value().clone());
+            c.move_next();
+        }
+        assert!(forward.iter().eq(t.iter().map(|e| e.value())));
+        c.move_prev();
+        assert!(c.element().unwrap().value() == "z");
+    }
+
+    #[test]
+    fn cursor_mut_removes() {
+        let mut t = setup_standard_treap();
+        {
+            let mut c = t.cursor_at_mut(&"cc".into());
+            c.move_prev();
+            assert!(c.element().unwrap().value() == "A");
+            c.move_next();
+            let removed = c.remove_current().unwrap();
+            assert!(removed.value() == "cc");
+            assert!(c.element().unwrap().value() == "hi");
+            assert!(c.remove_current().unwrap().value() == "hi");
+            assert!(c.element().unwrap().value() == "lo");
+            c.move_prev();
+            assert!(c.element().unwrap().value() == "A");
+        }
+        assert!(t.maintains_heap());
+        assert!(t.size() == 5);
+        assert!(t.get("cc".into()).is_none() && t.get("hi".into()).is_none());
+        assert!(t.iter().count() == 5);
+        // Remove everything from the back.
+        {
+            let mut c = t.cursor_front_mut();
+            c.move_prev();
+            c.move_prev();
+            while c.remove_current().is_some() {
+                assert!(c.element().is_none());
+                c.move_prev();
+            }
+        }
+        assert!(t.size() == 0 && t.get_max().is_none());
+    }
+
+    #[test]
+    fn concurrent_readers_see_increasing_max() {
+        let t = ConcurrentTreap::new();
+        std::thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..2000u32 {
+                    t.insert(Element::new(i % 97, i));
+                }
+            });
+            for _ in 0..4 {
+                s.spawn(|| {
+                    let mut last = None;
+                    for _ in 0..2000 {
+                        let m = t.get_max().map(|e| *e.priority());
+                        assert!(m >= last);
+                        last = m;
+                    }
+                });
+            }
+        });
+        assert!(t.size() == 97);
+        assert!(*t.get_max().unwrap().priority() == 1999);
+        assert!(t.get(&(1999 % 97)).is_some());
+        let t = t.into_inner();
+        assert!(t.iter().count() == t.size());
+        assert!(t.maintains_heap());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn from_sorted_builds_a_treap() {
+        let t = setup_standard_treap();
+        let r = Treap::from_sorted(
+            t.iter()
+                .map(|e| Element::new(e.value().clone(), *e.priority())),
+        )
+        .unwrap();
+        assert!(r.size() == t.size());
+        assert!(r.maintains_heap());
+        assert!(r.to_string() == t.to_string());
+        let e = Treap::from_sorted([Element::new(2, 0), Element::new(1, 0)]);
+        assert!(e.err() == Some(InvalidTreap::Unsorted));
+        let e = Treap::from_sorted([Element::new(1, 0.0), Element::new(2, f64::NAN)]);
+        assert!(e.err() == Some(InvalidTreap::HeapOrder));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn from_sorted_handles_edge_cases() {
+        let t = Treap::<u64, u64>::from_sorted([]).unwrap();
+        assert!(t.size() == 0 && t.height() == 0);
+        let e = Treap::from_sorted([Element::new(1, 0), Element::new(1, 0)]);
+        assert!(e.err() == Some(InvalidTreap::Unsorted));
+        // Equal priorities are allowed.
+        let t = Treap::from_sorted((0..10).map(|v| Element::new(v, 0))).unwrap();
+        assert!(t.size() == 10 && t.maintains_heap());
+        // Increasing priorities give a left spine, with the last element at the root.
+        let t = Treap::from_sorted((0..10).map(|v| Element::new(v, v))).unwrap();
+        assert!(t.height() == 10 && *t.get_max().unwrap().value() == 9);
+        assert!(t.iter().map(|e| *e.value()).eq(0..10));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trips() {
+        let t = setup_standard_treap();
+        let json = serde_json::to_string(&t).unwrap();
+        let r: Treap<String, i32> = serde_json::from_str(&json).unwrap();
+        assert!(r.size() == t.size());
+        assert!(r.maintains_heap());
+        assert!(r.to_string() == t.to_string());
+        // Out of order values and heap violations are rejected.
+        assert!(serde_json::from_str::<Treap<i32, i32>>("[[2,0],[1,0]]").is_err());
+        assert!(serde_json::from_str::<Treap<i32, i32>>("[[1,0],[1,0]]").is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn shape_round_trips() {
+        let mut t: Treap<i32, i32> = Treap::new();
+        // Equal priorities, so the shape depends on insertion order.
+        for i in [5, 2, 8, 1, 9, 3] {
+            t.insert(Element::new(i, 0));
+        }
+        let mut json = vec![];
+        shape::serialize(&t, &mut serde_json::Serializer::new(&mut json)).unwrap();
+        let r: Treap<i32, i32> =
+            shape::deserialize(&mut serde_json::Deserializer::from_slice(&json)).unwrap();
+        assert!(r.to_string() == t.to_string());
+        assert!(r.size() == 6 && r.height() == t.height());
+        let bad = r#"{"children":[1],"elements":[[1,0],[2,0]]}"#;
+        let e = shape::deserialize::<i32, i32, _>(&mut serde_json::Deserializer::from_str(bad));
+        assert!(e.is_err());
+        let bad = r#"{"children":[1],"elements":[[2,0],[1,5]]}"#;
+        let e = shape::deserialize::<i32, i32, _>(&mut serde_json::Deserializer::from_str(bad));
+        assert!(e.is_err());
+        let bad = r#"{"children":[2],"elements":[[1,0]]}"#;
+        let e = shape::deserialize::<i32, i32, _>(&mut serde_json::Deserializer::from_str(bad));
+        assert!(e.is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn random_set_works() {
+        use rand::{rngs::StdRng, SeedableRng};
+        let mut s = RandomTreapSet::new(StdRng::seed_from_u64(0));
+        for i in 0..1000 {
+            assert!(s.insert(i));
+        }
+        assert!(!s.insert(10));
+        assert!(s.len() == 1000);
+        assert!(s.treap().maintains_heap());
+        // A balanced tree of 1000 elements should be far shallower than 1000.
+        assert!(s.treap().height() < 50);
+        for i in (0..1000).step_by(2) {
+            assert!(s.remove(&i));
+        }
+        assert!(!s.remove(&0));
+        assert!(!s.contains(&0) && s.contains(&1));
+        assert!(s.iter().copied().eq((1..1000).step_by(2)));
+    }
+
+    #[test]
+    fn shape_is_correct() {
+        let mut t: Treap<i32, i32> = Treap::new();
+        assert!(t.height() == 0);
+        assert!(t.average_depth().is_none());
+        // Increasing priorities with increasing values produce a left spine.
+        for i in 0..4 {
+            t.insert(Element::new(i, i));
+        }
+        assert!(t.maintains_heap());
+        assert!(t.height() == 4);
+        assert!(t.average_depth() == Some(2.5));
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_are_recorded() {
+        let mut t = setup_standard_treap();
+        let s = t.stats();
+        assert!(s.operations == 7);
+        assert!(s.total.rotations > 0);
+        t.get("lo".into());
+        let s = t.stats();
+        assert!(s.operations == 8);
+        assert!(s.last.rotations == 0);
+        assert!(s.last.nodes_visited > 0);
+        assert!(s.last.comparisons == s.last.nodes_visited);
+        let depth = s.last.max_depth;
+        t.cursor_at_mut(&"lo".into());
+        let s = t.stats();
+        assert!(s.operations == 9);
+        assert!(s.last.max_depth == depth);
+        t.reset_stats();
+        assert!(t.stats().operations == 0);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats_match_known_treap() {
+        let op = |rotations, comparisons, nodes_visited, max_depth| OpStats {
+            rotations,
+            comparisons,
+            nodes_visited,
+            max_depth,
+        };
+        let mut t = Treap::new();
+        t.insert(Element::new(2, 30));
+        assert!(t.stats().last == op(0, 0, 1, 1));
+        t.insert(Element::new(1, 20));
+        t.insert(Element::new(3, 10));
+        // 4 is placed below 2 and 3.
+        t.insert(Element::new(4, 5));
+        assert!(t.stats().last == op(0, 2, 3, 3));
+        t.get(4);
+        assert!(t.stats().last == op(0, 3, 3, 3));
+        // 0 is placed below 2 and 1, and rotated above 1.
+        t.insert(Element::new(0, 25));
+        assert!(t.stats().last == op(1, 2, 3, 3));
+        // The root sinks three levels before it is removed.
+        assert!(t.delete(&2));
+        assert!(t.stats().last == op(3, 1, 4, 4));
+        assert!(t.maintains_heap() && t.size() == 4);
+        // The treap is now the path 0, 1, 3, 4, which the search follows.
+        assert!(!t.delete(&9));
+        assert!(t.stats().last == op(0, 4, 4, 4));
+        let s = t.stats();
+        assert!(s.operations == 8 && s.total.max_depth == 4);
+    }
 }
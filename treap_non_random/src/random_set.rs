@@ -0,0 +1,88 @@
+use rand::Rng;
+
+use crate::{Element, Treap};
+
+/// An ordered set built on a treap with random priorities.
+///
+/// Unlike `Treap`, where callers choose priorities, `RandomTreapSet` draws a
+/// priority for each value from `R`, so the tree is balanced in expectation
+/// and `insert`, `remove` and `contains` take expected O(lg n) time.
+///
+/// # Example
+/// ```
+/// use treap_non_random::RandomTreapSet;
+///
+/// let mut s = RandomTreapSet::new(rand::thread_rng());
+/// assert!(s.insert(3));
+/// assert!(s.insert(1));
+/// assert!(!s.insert(3));
+/// assert!(s.contains(&1));
+/// assert!(s.remove(&1));
+/// assert!(!s.contains(&1));
+/// assert_eq!(s.iter().copied().collect::<Vec<_>>(), vec![3]);
+/// ```
+pub struct RandomTreapSet<T: Ord, R: Rng> {
+    treap: Treap<T, u64>,
+    rng: R,
+}
+
+impl<T, R> RandomTreapSet<T, R>
+where
+    T: Ord,
+    R: Rng,
+{
+    /// Create a new, empty set which draws priorities from `r`.
+    pub fn new(r: R) -> Self {
+        RandomTreapSet {
+            treap: Treap::new(),
+            rng: r,
+        }
+    }
+
+    /// Insert `t` into the set. Returns `false` if `t` was already present,
+    /// in which case the set is unchanged.
+    pub fn insert(&mut self, t: T) -> bool {
+        if self.treap.contains(&t) {
+            false
+        } else {
+            let priority = self.rng.gen::<u64>();
+            self.treap.insert(Element::new(t, priority));
+            true
+        }
+    }
+
+    /// Remove `t` from the set. Returns `true` if `t` was present.
+    pub fn remove(&mut self, t: &T) -> bool {
+        self.treap.delete(t)
+    }
+
+    /// Check whether `t` is in the set.
+    pub fn contains(&self, t: &T) -> bool {
+        self.treap.contains(t)
+    }
+
+    /// Iterate over the values in the set in increasing order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &T> {
+        self.treap.iter().map(|e| e.value())
+    }
+
+    /// Get the number of values in the set.
+    pub fn len(&self) -> usize {
+        self.treap.size()
+    }
+
+    /// Check whether the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.treap.size() == 0
+    }
+
+    /// Remove all values from the set.
+    pub fn clear(&mut self) {
+        self.treap.reset();
+    }
+
+    /// Get a reference to the underlying treap.
+    pub fn treap(&self) -> &Treap<T, u64> {
+        &self.treap
+    }
+}
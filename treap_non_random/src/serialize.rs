@@ -0,0 +1,155 @@
+//! Serde support for `Treap`.
+//!
+//! A treap is serialized as the sequence of its elements in value order.
+//! Deserialization rebuilds the tree with `Treap::from_sorted()`, in linear
+//! time (as a Cartesian tree) rather than inserting elements one at a time,
+//! and rejects input that would violate either the ordering or the heap
+//! invariant.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display, Formatter};
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+
+use crate::data::Element;
+use crate::treap_node::TreapNode;
+use crate::Treap;
+
+impl<T, P> Serialize for Treap<T, P>
+where
+    T: Ord + Serialize,
+    P: PartialOrd + Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T, P> Deserialize<'de> for Treap<T, P>
+where
+    T: Ord + Deserialize<'de>,
+    P: PartialOrd + Deserialize<'de>,
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let elements = Vec::<Element<T, P>>::deserialize(deserializer)?;
+        Treap::from_sorted(elements).map_err(D::Error::custom)
+    }
+}
+
+/// The reason a sequence of elements does not form a valid treap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InvalidTreap {
+    /// Values are not strictly increasing.
+    Unsorted,
+    /// An element has a higher priority than its parent, or priorities
+    /// cannot be compared (e.g., NaN).
+    HeapOrder,
+}
+
+impl Display for InvalidTreap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            InvalidTreap::Unsorted => {
+                write!(f, "treap elements are not in strictly increasing order")
+            }
+            InvalidTreap::HeapOrder => {
+                write!(f, "treap element has a higher priority than its parent")
+            }
+        }
+    }
+}
+
+impl std::error::Error for InvalidTreap {}
+
+impl<T, P> Treap<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    /// Build a treap from elements in strictly increasing order of value, in
+    /// linear time and without rotating, as deserialization does.
+    ///
+    /// # Errors
+    /// Returns `InvalidTreap::Unsorted` if the values are not strictly
+    /// increasing, and `InvalidTreap::HeapOrder` if priorities cannot be
+    /// compared.
+    ///
+    /// # Example
+    /// ```
+    /// use treap_non_random::{Element, InvalidTreap, Treap};
+    ///
+    /// let t = Treap::from_sorted([Element::new(1, 0), Element::new(2, 5), Element::new(3, 1)]);
+    /// assert!(t.unwrap().get_max().unwrap().value() == &2);
+    /// let e = Treap::from_sorted([Element::new(2, 0), Element::new(1, 0)]);
+    /// assert!(e.err() == Some(InvalidTreap::Unsorted));
+    /// ```
+    pub fn from_sorted<I>(elements: I) -> Result<Self, InvalidTreap>
+    where
+        I: IntoIterator<Item = Element<T, P>>,
+    {
+        let mut size = 0;
+        // The right spine of the tree built so far. Each node's right child is
+        // the node above it on the stack.
+        let mut spine: Vec<Box<TreapNode<T, P>>> = Vec::new();
+        for e in elements {
+            if spine.last().is_some_and(|n| n.element.value() >= e.value()) {
+                return Err(InvalidTreap::Unsorted);
+            }
+            let mut node = Box::new(TreapNode::from(e));
+            let mut last = None;
+            while spine
+                .last()
+                .is_some_and(|n| n.element.priority() < node.element.priority())
+            {
+                let mut n = spine.pop().unwrap();
+                n.right = last;
+                last = Some(n);
+            }
+            node.left = last;
+            spine.push(node);
+            size += 1;
+        }
+        let mut root = None;
+        while let Some(mut n) = spine.pop() {
+            n.right = root;
+            root = Some(n);
+        }
+        let mut treap = Treap::new();
+        treap.root = root;
+        treap.size = size;
+        check_invariants(&treap)?;
+        Ok(treap)
+    }
+}
+
+/// Check that values are strictly increasing in order and that no element
+/// has a higher priority than its parent. Priorities that cannot be compared
+/// (e.g., NaN) are rejected.
+pub(crate) fn check_invariants<T, P>(treap: &Treap<T, P>) -> Result<(), InvalidTreap>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    let mut prev: Option<&T> = None;
+    for e in treap.iter() {
+        if prev.is_some_and(|p| p >= e.value()) {
+            return Err(InvalidTreap::Unsorted);
+        }
+        prev = Some(e.value());
+    }
+    let mut stack: Vec<&TreapNode<T, P>> = treap.root.as_deref().into_iter().collect();
+    while let Some(n) = stack.pop() {
+        for c in [n.left.as_deref(), n.right.as_deref()]
+            .into_iter()
+            .flatten()
+        {
+            let ord = c.element.priority().partial_cmp(n.element.priority());
+            if !matches!(ord, Some(Ordering::Less | Ordering::Equal)) {
+                return Err(InvalidTreap::HeapOrder);
+            }
+            stack.push(c);
+        }
+    }
+    Ok(())
+}
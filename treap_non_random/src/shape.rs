@@ -0,0 +1,147 @@
+//! A compact serde encoding for `Treap` that preserves the shape of the tree.
+//!
+//! The default `Serialize` implementation stores elements in value order, and
+//! deserializing rebuilds a treap with the same shape only because the shape
+//! of a treap is determined by its values and priorities when priorities are
+//! distinct. With ties the rebuilt tree can differ. This encoding instead
+//! stores elements in pre-order along with two bits per element recording
+//! which children it has, so the tree is reloaded exactly as it was saved.
+//! Reloading does not compare or rotate elements beyond checking the
+//! invariants.
+//!
+//! Use it with serde's `with` attribute:
+//! ```
+//! use serde::{Deserialize, Serialize};
+//! use treap_non_random::{Element, Treap};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Checkpoint {
+//!     #[serde(with = "treap_non_random::shape")]
+//!     treap: Treap<u32, u32>,
+//! }
+//!
+//! let mut treap = Treap::new();
+//! treap.insert(Element::new(1, 10));
+//! treap.insert(Element::new(2, 20));
+//! let json = serde_json::to_string(&Checkpoint { treap }).unwrap();
+//! let c: Checkpoint = serde_json::from_str(&json).unwrap();
+//! assert_eq!(*c.treap.get_max().unwrap().value(), 2);
+//! ```
+
+use serde::de::{Deserialize, Deserializer, Error};
+use serde::ser::{Serialize, Serializer};
+use serde::{Deserialize as DeserializeDerive, Serialize as SerializeDerive};
+
+use crate::data::Element;
+use crate::serialize::check_invariants;
+use crate::treap_node::TreapNode;
+use crate::Treap;
+
+const HAS_LEFT: u8 = 1;
+const HAS_RIGHT: u8 = 2;
+
+#[derive(SerializeDerive)]
+struct ShapeRef<'a, T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    children: Vec<u8>,
+    elements: Vec<&'a Element<T, P>>,
+}
+
+#[derive(DeserializeDerive)]
+struct Shape<T, P>
+where
+    T: Ord,
+    P: PartialOrd,
+{
+    children: Vec<u8>,
+    elements: Vec<Element<T, P>>,
+}
+
+/// Get the child flags for the `i`th element in pre-order.
+fn flags(children: &[u8], i: usize) -> Option<u8> {
+    children.get(i / 4).map(|b| (b >> ((i % 4) * 2)) & 3)
+}
+
+/// Serialize `treap` preserving its shape.
+pub fn serialize<T, P, S>(treap: &Treap<T, P>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: Ord + Serialize,
+    P: PartialOrd + Serialize,
+    S: Serializer,
+{
+    let mut shape = ShapeRef {
+        children: vec![0; treap.size().div_ceil(4)],
+        elements: Vec::with_capacity(treap.size()),
+    };
+    let mut stack: Vec<&TreapNode<T, P>> = treap.root.as_deref().into_iter().collect();
+    while let Some(n) = stack.pop() {
+        let i = shape.elements.len();
+        let mut f = 0;
+        if let Some(r) = n.right.as_deref() {
+            f |= HAS_RIGHT;
+            stack.push(r);
+        }
+        if let Some(l) = n.left.as_deref() {
+            f |= HAS_LEFT;
+            stack.push(l);
+        }
+        shape.children[i / 4] |= f << ((i % 4) * 2);
+        shape.elements.push(&n.element);
+    }
+    shape.serialize(serializer)
+}
+
+/// Deserialize a treap written by `serialize`, checking that it satisfies
+/// the treap invariants.
+pub fn deserialize<'de, T, P, D>(deserializer: D) -> Result<Treap<T, P>, D::Error>
+where
+    T: Ord + Deserialize<'de>,
+    P: PartialOrd + Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    let shape = Shape::<T, P>::deserialize(deserializer)?;
+    let n = shape.elements.len();
+    // Find the children of each element. Elements are in pre-order, so a
+    // stack of unfilled child slots tells us where each element goes.
+    let mut left = vec![None; n];
+    let mut right = vec![None; n];
+    let mut slots: Vec<(usize, u8)> = Vec::new();
+    for i in 0..n {
+        if i > 0 {
+            match slots.pop() {
+                Some((p, HAS_LEFT)) => left[p] = Some(i),
+                Some((p, _)) => right[p] = Some(i),
+                None => return Err(D::Error::custom("treap shape has too many elements")),
+            }
+        }
+        let f = flags(&shape.children, i)
+            .ok_or_else(|| D::Error::custom("treap shape is truncated"))?;
+        if f & HAS_RIGHT != 0 {
+            slots.push((i, HAS_RIGHT));
+        }
+        if f & HAS_LEFT != 0 {
+            slots.push((i, HAS_LEFT));
+        }
+    }
+    if !slots.is_empty() {
+        return Err(D::Error::custom("treap shape has too few elements"));
+    }
+    // Children come after their parents in pre-order, so build from the back.
+    let mut built: Vec<Option<Box<TreapNode<T, P>>>> = Vec::new();
+    built.resize_with(n, || None);
+    let mut elements = shape.elements;
+    for i in (0..n).rev() {
+        let mut node = TreapNode::from(elements.pop().unwrap());
+        node.left = left[i].and_then(|c| built[c].take());
+        node.right = right[i].and_then(|c| built[c].take());
+        built[i] = Some(Box::new(node));
+    }
+    let mut treap = Treap::new();
+    treap.root = built.into_iter().next().flatten();
+    treap.size = n;
+    check_invariants(&treap).map_err(D::Error::custom)?;
+    Ok(treap)
+}
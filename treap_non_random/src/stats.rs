@@ -0,0 +1,187 @@
+//! Operation statistics for a `Treap`.
+//!
+//! Since priorities are not random, the shape of the treap depends entirely
+//! on the workload. When the `stats` feature is enabled the treap counts
+//! rotations, comparisons and visited nodes for every operation so that the
+//! balance of real workloads can be measured. When the feature is disabled
+//! the recorder is an empty type and all of its methods compile away.
+
+#[cfg(feature = "stats")]
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+/// Counters for one or more treap operations.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OpStats {
+    /// Number of rotations performed.
+    pub rotations: u64,
+    /// Number of key comparisons performed.
+    pub comparisons: u64,
+    /// Number of nodes visited.
+    pub nodes_visited: u64,
+    /// The deepest node reached, where the root has depth 1.
+    pub max_depth: u64,
+}
+
+/// Statistics collected by a `Treap`, returned by `Treap::stats()`.
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Number of operations (`insert`, `get`, `delete`, and seeking or
+    /// removing with a `CursorMut`) recorded.
+    pub operations: u64,
+    /// Counters summed over all operations. `max_depth` is the maximum
+    /// over all operations.
+    pub total: OpStats,
+    /// Counters for the most recent operation.
+    ///
+    /// Only meaningful while operations do not overlap. `get` takes `&self`,
+    /// so concurrent readers, e.g., of a `ConcurrentTreap`, share these
+    /// counters, and `last` and `total.max_depth` then mix several
+    /// operations. The other totals are exact.
+    pub last: OpStats,
+}
+
+#[cfg(feature = "stats")]
+impl Stats {
+    /// Average number of rotations per operation.
+    pub fn rotations_per_op(&self) -> f64 {
+        self.per_op(self.total.rotations)
+    }
+
+    /// Average number of comparisons per operation.
+    pub fn comparisons_per_op(&self) -> f64 {
+        self.per_op(self.total.comparisons)
+    }
+
+    /// Average number of nodes visited per operation.
+    pub fn nodes_visited_per_op(&self) -> f64 {
+        self.per_op(self.total.nodes_visited)
+    }
+
+    fn per_op(&self, count: u64) -> f64 {
+        if self.operations == 0 {
+            0.0
+        } else {
+            count as f64 / self.operations as f64
+        }
+    }
+}
+
+/// Records statistics as the treap is walked. Every operation walks a single
+/// path from the root, so the depth reached is the number of nodes visited.
+/// Counters are atomics so that a `Treap` remains `Sync`; `get` can update
+/// them through a shared reference. The depth is derived from the nodes
+/// visited since `begin()`, so it is only exact for a single thread.
+#[cfg(feature = "stats")]
+pub(crate) struct Recorder {
+    operations: AtomicU64,
+    total: Counters,
+    last: Counters,
+}
+
+#[cfg(feature = "stats")]
+struct Counters {
+    rotations: AtomicU64,
+    comparisons: AtomicU64,
+    nodes_visited: AtomicU64,
+    max_depth: AtomicU64,
+}
+
+#[cfg(feature = "stats")]
+impl Counters {
+    const fn new() -> Self {
+        Counters {
+            rotations: AtomicU64::new(0),
+            comparisons: AtomicU64::new(0),
+            nodes_visited: AtomicU64::new(0),
+            max_depth: AtomicU64::new(0),
+        }
+    }
+
+    fn get(&self) -> OpStats {
+        OpStats {
+            rotations: self.rotations.load(Relaxed),
+            comparisons: self.comparisons.load(Relaxed),
+            nodes_visited: self.nodes_visited.load(Relaxed),
+            max_depth: self.max_depth.load(Relaxed),
+        }
+    }
+
+    fn reset(&self) {
+        self.rotations.store(0, Relaxed);
+        self.comparisons.store(0, Relaxed);
+        self.nodes_visited.store(0, Relaxed);
+        self.max_depth.store(0, Relaxed);
+    }
+}
+
+#[cfg(feature = "stats")]
+impl Recorder {
+    pub const fn new() -> Self {
+        Recorder {
+            operations: AtomicU64::new(0),
+            total: Counters::new(),
+            last: Counters::new(),
+        }
+    }
+
+    pub fn begin(&self) {
+        self.operations.fetch_add(1, Relaxed);
+        self.last.reset();
+    }
+
+    pub fn rotation(&self) {
+        self.total.rotations.fetch_add(1, Relaxed);
+        self.last.rotations.fetch_add(1, Relaxed);
+    }
+
+    pub fn comparison(&self) {
+        self.total.comparisons.fetch_add(1, Relaxed);
+        self.last.comparisons.fetch_add(1, Relaxed);
+    }
+
+    pub fn visit(&self) {
+        self.total.nodes_visited.fetch_add(1, Relaxed);
+        let depth = self.last.nodes_visited.fetch_add(1, Relaxed) + 1;
+        self.last.max_depth.fetch_max(depth, Relaxed);
+        self.total.max_depth.fetch_max(depth, Relaxed);
+    }
+
+    pub fn get(&self) -> Stats {
+        Stats {
+            operations: self.operations.load(Relaxed),
+            total: self.total.get(),
+            last: self.last.get(),
+        }
+    }
+
+    pub fn reset(&self) {
+        self.operations.store(0, Relaxed);
+        self.total.reset();
+        self.last.reset();
+    }
+}
+
+#[cfg(not(feature = "stats"))]
+pub(crate) struct Recorder;
+
+#[cfg(not(feature = "stats"))]
+impl Recorder {
+    #[inline(always)]
+    pub const fn new() -> Self {
+        Recorder
+    }
+
+    #[inline(always)]
+    pub fn begin(&self) {}
+
+    #[inline(always)]
+    pub fn rotation(&self) {}
+
+    #[inline(always)]
+    pub fn comparison(&self) {}
+
+    #[inline(always)]
+    pub fn visit(&self) {}
+}
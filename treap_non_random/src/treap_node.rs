@@ -1,4 +1,5 @@
 use std::{
+    borrow::Borrow,
     cmp::Ordering,
     fmt::{Display, Formatter, Result},
     mem,
@@ -7,6 +8,7 @@ use std::{
 use alloc_counter::no_alloc;
 
 use crate::data::Element;
+use crate::stats::Recorder;
 use std::convert::From;
 
 pub struct TreapNode<T, P>
@@ -19,7 +21,8 @@ where
     pub right: Option<Box<TreapNode<T, P>>>,
 }
 
-enum TreapChild {
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum TreapChild {
     Left,
     Right,
 }
@@ -29,29 +32,32 @@ where
     T: Ord,
     P: PartialOrd,
 {
-    fn left_insert(&mut self, node: Self) -> bool {
+    fn left_insert(&mut self, node: Self, stats: &Recorder) -> bool {
         match &mut self.left {
             None => {
+                stats.visit();
                 let _ = mem::replace(&mut self.left, Some(Box::new(node)));
                 true
             }
-            Some(e) => e.insert_or_replace(node),
+            Some(e) => e.insert_or_replace(node, stats),
         }
     }
 
-    fn right_insert(&mut self, node: Self) -> bool {
+    fn right_insert(&mut self, node: Self, stats: &Recorder) -> bool {
         match &mut self.right {
             None => {
+                stats.visit();
                 let _ = mem::replace(&mut self.right, Some(Box::new(node)));
                 true
             }
-            Some(e) => e.insert_or_replace(node),
+            Some(e) => e.insert_or_replace(node, stats),
         }
     }
 
     /// Rotate the tree right.
     #[no_alloc]
-    pub fn rotate_right(&mut self) {
+    pub fn rotate_right(&mut self, stats: &Recorder) {
+        stats.rotation();
         // Get left subtree.
         let l = mem::take(&mut self.left);
         if let Some(mut p) = l {
@@ -66,7 +72,8 @@ where
 
     /// Rotate the tree left.
     #[no_alloc]
-    pub fn rotate_left(&mut self) {
+    pub fn rotate_left(&mut self, stats: &Recorder) {
+        stats.rotation();
         let r = mem::take(&mut self.right);
         if let Some(mut q) = r {
             // Self now points to the right subtree
@@ -89,30 +96,56 @@ where
         }
     }
 
+    /// Rotate this node down until neither child has a higher priority.
+    fn sift_down(&mut self, stats: &Recorder) {
+        let next = match (&self.left, &self.right) {
+            (Some(l), Some(r)) if !self.heap_check(&self.left) || !self.heap_check(&self.right) => {
+                if l.element.priority() < r.element.priority() {
+                    TreapChild::Left
+                } else {
+                    TreapChild::Right
+                }
+            }
+            (Some(_), _) if !self.heap_check(&self.left) => TreapChild::Right,
+            (_, Some(_)) if !self.heap_check(&self.right) => TreapChild::Left,
+            _ => return,
+        };
+        match next {
+            TreapChild::Left => {
+                self.rotate_left(stats);
+                self.left.as_deref_mut().unwrap().sift_down(stats);
+            }
+            TreapChild::Right => {
+                self.rotate_right(stats);
+                self.right.as_deref_mut().unwrap().sift_down(stats);
+            }
+        }
+    }
+
     /// Insert a new node or modify an existing one.
     /// Return true if a new node is inserted.
-    pub fn insert_or_replace(&mut self, node: Self) -> bool {
+    pub fn insert_or_replace(&mut self, node: Self, stats: &Recorder) -> bool {
+        stats.visit();
+        stats.comparison();
         match self.element.value().cmp(node.element.value()) {
             Ordering::Equal => {
-                let _ = mem::replace(self, node);
-                if !self.heap_check(&self.left) {
-                    self.rotate_right()
-                } else if !self.heap_check(&self.right) {
-                    self.rotate_left()
-                };
+                // Keep the subtrees. A higher priority is fixed by the callers
+                // as the node moves up, a lower one by moving it down.
+                self.element = node.element;
+                self.sift_down(stats);
                 false
             }
             Ordering::Greater => {
-                let r = self.left_insert(node);
+                let r = self.left_insert(node, stats);
                 if !self.heap_check(&self.left) {
-                    self.rotate_right()
+                    self.rotate_right(stats)
                 };
                 r
             }
             Ordering::Less => {
-                let r = self.right_insert(node);
+                let r = self.right_insert(node, stats);
                 if !self.heap_check(&self.right) {
-                    self.rotate_left()
+                    self.rotate_left(stats)
                 };
                 r
             }
@@ -144,19 +177,25 @@ where
     /// get with priorities, the tree is not set up to make that
     /// lookup efficient.
     #[no_alloc]
-    pub fn get(&self, e: T) -> Option<&Element<T, P>> {
-        match &self.element.value().cmp(&e) {
+    pub fn get<Q>(&self, e: &Q, stats: &Recorder) -> Option<&Element<T, P>>
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        stats.visit();
+        stats.comparison();
+        match self.element.value().borrow().cmp(e) {
             Ordering::Equal => Some(&self.element),
             Ordering::Greater => {
                 if let Some(l) = self.left.as_ref() {
-                    l.get(e)
+                    l.get(e, stats)
                 } else {
                     None
                 }
             }
             Ordering::Less => {
                 if let Some(r) = self.right.as_ref() {
-                    r.get(e)
+                    r.get(e, stats)
                 } else {
                     None
                 }
@@ -164,80 +203,95 @@ where
         }
     }
 
-    /// Delete a node with element `e`. Note, we cannot delete the root itself,
-    /// for one we might have nothing to replace it with. The Treap itself takes
-    /// care of this problem.
+    /// Delete the node with element `e` from below this node, where `ord`
+    /// is the result of comparing this node's element with `e`. Note, we
+    /// cannot delete this node itself, for one we might have nothing to
+    /// replace it with. The Treap itself takes care of this problem.
     #[no_alloc]
-    pub fn delete(&mut self, e: &T) -> bool {
-        match &self.element.value().cmp(e) {
+    pub fn delete<Q>(&mut self, ord: Ordering, e: &Q, stats: &Recorder) -> bool
+    where
+        T: Borrow<Q>,
+        Q: Ord + ?Sized,
+    {
+        let slot = match ord {
             Ordering::Equal => {
                 panic!("You don't want to do this, it is bad idea.")
             }
-            Ordering::Greater => {
-                if self.left.is_some() {
-                    if self.left.as_ref().unwrap().element.value() == e {
-                        self.delete_child(TreapChild::Left);
-                        true
-                    } else {
-                        self.left.as_deref_mut().unwrap().delete(e)
-                    }
-                } else {
-                    false
-                }
-            }
-            Ordering::Less => {
-                if self.right.is_some() {
-                    if self.right.as_ref().unwrap().element.value() == e {
-                        self.delete_child(TreapChild::Right);
+            Ordering::Greater => &mut self.left,
+            Ordering::Less => &mut self.right,
+        };
+        match slot.as_deref_mut() {
+            None => false,
+            Some(c) => {
+                stats.visit();
+                stats.comparison();
+                match c.element.value().borrow().cmp(e) {
+                    Ordering::Equal => {
+                        Self::take_root(slot, stats);
                         true
-                    } else {
-                        self.right.as_deref_mut().unwrap().delete(e)
                     }
-                } else {
-                    false
+                    ord => c.delete(ord, e, stats),
                 }
             }
         }
     }
 
+    /// Remove the root of the subtree in `slot` and return its element. The
+    /// root is rotated down until it is a leaf, which preserves the heap
+    /// property of the remaining subtree. The caller has already visited the
+    /// root, and each level it moves down is recorded as another visit.
     #[no_alloc]
-    fn delete_child(&mut self, child: TreapChild) {
-        let done = {
-            let which = match child {
-                TreapChild::Left => self.left.as_deref_mut().unwrap(),
-                TreapChild::Right => self.right.as_deref_mut().unwrap(),
-            };
-            if which.left.is_none() && which.right.is_none() {
-                true
-            } else if which.left.is_none() {
-                which.rotate_left();
-                which.delete_child(TreapChild::Left);
-                false
-            } else if which.right.is_none() {
-                which.rotate_right();
-                which.delete_child(TreapChild::Right);
-                false
-            } else {
-                let p_left = which.left.as_ref().unwrap().element.priority();
-                let p_right = which.right.as_ref().unwrap().element.priority();
-                if p_left < p_right {
-                    which.rotate_left();
-                    which.delete_child(TreapChild::Left);
+    pub fn take_root(slot: &mut Option<Box<Self>>, stats: &Recorder) -> Option<Element<T, P>> {
+        let node = slot.as_mut()?;
+        let next = match (&node.left, &node.right) {
+            (None, None) => return slot.take().map(|n| n.element),
+            (None, Some(_)) => TreapChild::Left,
+            (Some(_), None) => TreapChild::Right,
+            (Some(l), Some(r)) => {
+                if l.element.priority() < r.element.priority() {
+                    TreapChild::Left
                 } else {
-                    which.rotate_right();
-                    which.delete_child(TreapChild::Right);
+                    TreapChild::Right
                 }
-                false
             }
         };
-        if done {
-            match child {
-                TreapChild::Left => mem::take(&mut self.left),
-                TreapChild::Right => mem::take(&mut self.right),
-            };
+        match next {
+            TreapChild::Left => {
+                node.rotate_left(stats);
+                stats.visit();
+                Self::take_root(&mut node.left, stats)
+            }
+            TreapChild::Right => {
+                node.rotate_right(stats);
+                stats.visit();
+                Self::take_root(&mut node.right, stats)
+            }
         }
     }
 
+    /// Height of the subtree rooted at this node, counting this node.
+    pub fn height(&self) -> usize {
+        let l = self.left.as_ref().map(|n| n.height()).unwrap_or(0);
+        let r = self.right.as_ref().map(|n| n.height()).unwrap_or(0);
+        1 + l.max(r)
+    }
+
+    /// Sum of the depths of all nodes in this subtree, given that this
+    /// node is at `depth`.
+    pub fn depth_sum(&self, depth: usize) -> usize {
+        let l = self
+            .left
+            .as_ref()
+            .map(|n| n.depth_sum(depth + 1))
+            .unwrap_or(0);
+        let r = self
+            .right
+            .as_ref()
+            .map(|n| n.depth_sum(depth + 1))
+            .unwrap_or(0);
+        depth + l + r
+    }
+
     #[cfg(test)]
     pub fn maintains_heap(&self) -> bool {
         let left_test = self